@@ -1,13 +1,16 @@
 from __future__ import annotations
 
+import json
+import os
 from pathlib import Path
-from typing import TYPE_CHECKING, List
+from typing import TYPE_CHECKING, Dict, List
 
 from tokenizers import Tokenizer
 
 import polars as pl
 from polars.plugins import register_plugin_function
 
+import polars_textproc._internal as _internal
 from polars_textproc._internal import __version__ as __version__
 
 if TYPE_CHECKING:
@@ -51,24 +54,270 @@ SEED = [
 ]
 
 
-def tokenize(expr: IntoExprColumn, *, tokenizer: Tokenizer | str) -> pl.Expr:
+def _hf_hub_cache() -> Path:
+    cache = os.environ.get("HF_HUB_CACHE") or os.environ.get("HUGGINGFACE_HUB_CACHE")
+    if cache:
+        return Path(cache).expanduser()
+    hf_home = os.environ.get("HF_HOME") or os.path.join(
+        os.environ.get("XDG_CACHE_HOME", "~/.cache"), "huggingface"
+    )
+    return Path(hf_home).expanduser() / "hub"
+
+
+def _tokenizer_path(tokenizer: str) -> str:
+    """
+    Resolves `tokenizer` to the path of a `tokenizer.json`. If it is not a file, it is taken
+    to be a model name (optionally followed by `@revision`), and looked up in the local
+    Hugging Face cache, without accessing the network.
+    """
+    if os.path.isfile(tokenizer):
+        return tokenizer
+    name, _, revision = tokenizer.partition("@")
+    repo = _hf_hub_cache() / ("models--" + name.replace("/", "--"))
+    ref = repo / "refs" / (revision or "main")
+    commit = ref.read_text().strip() if ref.is_file() else revision
+    path = repo / "snapshots" / commit / "tokenizer.json" if commit else None
+    if path is None or not path.is_file():
+        raise ValueError(
+            f"tokenizer {tokenizer!r} is neither a file nor a model "
+            f"in the Hugging Face cache at {repo.parent}"
+        )
+    return str(path)
+
+
+def _tokenizer_kwargs(tokenizer: Tokenizer | str) -> dict:
     if isinstance(tokenizer, Tokenizer):
-        kwargs = {"payload": tokenizer.to_str(), "is_path": False}
+        return {"payload": tokenizer.to_str(), "is_path": False}
     elif isinstance(tokenizer, str):
-        kwargs = {
-            "payload": tokenizer,
+        return {
+            "payload": _tokenizer_path(tokenizer),
             "is_path": True,
         }
     else:
         raise ValueError(
             f"tokenizer must be str or Tokenizer instance, found: {type(tokenizer)}"
         )
+
+
+def tokenize(
+    expr: IntoExprColumn,
+    *,
+    tokenizer: Tokenizer | str,
+    add_special_tokens: bool = False,
+    output_offsets: bool = False,
+    output_tokens: bool = False,
+    output_type_ids: bool = False,
+    output_attention_mask: bool = False,
+    max_length: int | None = None,
+    truncation: str = "right",
+    stride: int = 0,
+    overflow: bool = False,
+    padding: bool = False,
+    padding_side: str = "right",
+    pad_id: int | None = None,
+    batch_size: int = 1024,
+) -> pl.Expr:
+    """
+    Tokenizes the given text column, returning the token ids as a `List(UInt32)`.
+    `tokenizer` is either a path to a json dump of a `tokenizers.Tokenizer`, a `tokenizers.Tokenizer`,
+    or the name of a model in the local Hugging Face cache (e.g. "bert-base-uncased" or "org/model@revision").
+    `add_special_tokens`: add the special tokens of the tokenizer's post-processor (e.g. `[CLS]`, `[SEP]`).
+
+    If any of the following outputs are requested, a struct is returned instead, with the ids in the `ids` field:
+    `output_offsets`        => `offsets`        : List(Struct{start, end}) = byte offsets of each token.
+    `output_tokens`         => `tokens`         : List(String)             = the token strings.
+    `output_type_ids`       => `type_ids`       : List(UInt32)             = the type (segment) ids.
+    `output_attention_mask` => `attention_mask` : List(UInt8)              = the attention mask.
+
+    Fixed length sequences, mirroring the truncation and padding parameters of `tokenizers`:
+    `max_length`: truncate to at most `max_length` tokens (including special tokens).
+    `truncation`: the side to truncate from, "left" or "right".
+    `stride`: the number of overlapping tokens between consecutive overflow windows.
+    `overflow`: return all windows of each text instead of only the first,
+        wrapping each output in an extra list (e.g. `List(List(UInt32))` for the ids).
+    `padding`: pad to `max_length` tokens, returning fixed size arrays (e.g. `Array(UInt32, max_length)`).
+    `padding_side`: the side to pad, "left" or "right".
+    `pad_id`: the padding token id (defaults to the tokenizer's padding id, or 0).
+
+    `batch_size`: the number of texts encoded in parallel at a time.
+    Parallelism can be disabled by setting the `TOKENIZERS_PARALLELISM` environment variable to `false`.
+    """
+    assert truncation in ("left", "right"), (
+        f"truncation must be one of 'left' or 'right', not {truncation!r}"
+    )
+    assert padding_side in ("left", "right"), (
+        f"padding_side must be one of 'left' or 'right', not {padding_side!r}"
+    )
+    assert max_length is not None or not (padding or overflow), (
+        "padding and overflow require max_length"
+    )
+    assert batch_size > 0, "batch_size must be positive"
     return register_plugin_function(
         args=[expr],
         plugin_path=LIB,
         function_name="tokenize",
         is_elementwise=True,
-        kwargs=kwargs,
+        kwargs={
+            **_tokenizer_kwargs(tokenizer),
+            "add_special_tokens": add_special_tokens,
+            "output_offsets": output_offsets,
+            "output_tokens": output_tokens,
+            "output_type_ids": output_type_ids,
+            "output_attention_mask": output_attention_mask,
+            "max_length": max_length,
+            "truncation": truncation,
+            "stride": stride,
+            "overflow": overflow,
+            "padding": padding,
+            "padding_side": padding_side,
+            "pad_id": pad_id,
+            "batch_size": batch_size,
+        },
+    )
+
+
+def token_count(
+    expr: IntoExprColumn, *, tokenizer: Tokenizer | str, add_special_tokens: bool = False
+) -> pl.Expr:
+    """
+    Counts the tokens of the given text column, without materializing the token ids.
+    Equivalent to `tokenize(expr, tokenizer=tokenizer).list.len()`, returned as `UInt32`.
+    """
+    return register_plugin_function(
+        args=[expr],
+        plugin_path=LIB,
+        function_name="token_count",
+        is_elementwise=True,
+        kwargs={
+            **_tokenizer_kwargs(tokenizer),
+            "add_special_tokens": add_special_tokens,
+        },
+    )
+
+
+def detokenize(
+    expr: IntoExprColumn, *, tokenizer: Tokenizer | str, skip_special_tokens: bool = False
+) -> pl.Expr:
+    """
+    Decodes a column of token ids (`List(UInt32)`) back into text, using the tokenizer's decoder.
+    The tokenizer is supplied as for `tokenize`.
+    `skip_special_tokens`: leave out special tokens (e.g. `[CLS]`, `[SEP]`) from the decoded text.
+    """
+    return register_plugin_function(
+        args=[expr],
+        plugin_path=LIB,
+        function_name="detokenize",
+        is_elementwise=True,
+        kwargs={
+            **_tokenizer_kwargs(tokenizer),
+            "skip_special_tokens": skip_special_tokens,
+        },
+    )
+
+
+def tokenizer_stats(
+    expr: IntoExprColumn,
+    *,
+    tokenizer: Tokenizer | str,
+    word_pattern: str = r"\w+",
+    word_tokenizer: str | None = None,
+) -> pl.Expr:
+    """
+    Computes statistics of the tokenization of the given text column, to compare tokenizers.
+    The tokenizer is supplied as for `tokenize`, and no special tokens are added.
+    Words are matches of `word_pattern` (a Rust regex), or the tokens of the built-in
+    `word_tokenizer` if given (see `WORD_TOKENIZERS`). Returns a struct with the fields
+    `num_tokens`, `num_words`, `tokens_per_word`, `tokens_per_byte`,
+    `unk_ratio` (fraction of unknown tokens), and `byte_fallback_ratio` (fraction of `<0xXX>` tokens).
+    Ratios are null when their denominator is zero.
+    """
+    _check_word_tokenizer(word_tokenizer)
+    return register_plugin_function(
+        args=[expr],
+        plugin_path=LIB,
+        function_name="tokenizer_stats",
+        is_elementwise=True,
+        kwargs={
+            **_tokenizer_kwargs(tokenizer),
+            "word_pattern": word_pattern,
+            "word_tokenizer": word_tokenizer,
+        },
+    )
+
+
+def pack_sequences(
+    expr: IntoExprColumn,
+    *,
+    seq_len: int,
+    eos_id: int,
+    pad_id: int = 0,
+    strategy: str = "greedy",
+    drop_last: bool = False,
+) -> pl.Expr:
+    """
+    Packs a column of token ids (`List` of integers) into fixed length training sequences.
+    Each document is followed by `eos_id`; returns a struct of `ids` and `segment_ids`,
+    both `Array(UInt32, seq_len)`, with one row per sequence.
+    """
+    assert seq_len > 0, "seq_len must be positive"
+    assert strategy in ("greedy", "best_fit"), "strategy must be 'greedy' or 'best_fit'"
+    return register_plugin_function(
+        args=[expr],
+        plugin_path=LIB,
+        function_name="pack_sequences",
+        is_elementwise=False,
+        changes_length=True,
+        kwargs={
+            "seq_len": seq_len,
+            "eos_id": eos_id,
+            "pad_id": pad_id,
+            "strategy": strategy,
+            "drop_last": drop_last,
+        },
+    )
+
+
+def train_tokenizer(
+    texts: pl.Series,
+    path: str,
+    *,
+    model: str = "bpe",
+    vocab_size: int = 30000,
+    min_frequency: int = 0,
+    special_tokens: List[str] | None = None,
+    unk_token: str | None = None,
+    lowercase: bool = False,
+    strip_accents: bool = False,
+    unicode_normalization: str | None = None,
+    pre_tokenizer: str | None = None,
+) -> None:
+    """
+    Trains a tokenizer on the (non-null) texts of a `String` series, and saves it as json to `path`,
+    which can then be used as the `tokenizer` of `tokenize`.
+    `model`: "bpe", "wordpiece", or "unigram".
+    `vocab_size`: the size of the vocabulary, including special tokens.
+    `min_frequency`: the minimum frequency of merged pairs (bpe and wordpiece only).
+    `special_tokens`: tokens added to the vocabulary, which are never split.
+    `unk_token`: the unknown token, added to the special tokens (wordpiece defaults to "[UNK]").
+    `lowercase`, `strip_accents`, `unicode_normalization` ("nfc", "nfd", "nfkc", "nfkd"): normalizer options.
+    `pre_tokenizer`: "byte_level", "whitespace", or "metaspace",
+        defaulting to "byte_level" for bpe, "whitespace" for wordpiece, and "metaspace" for unigram.
+    """
+    assert model in ("bpe", "wordpiece", "unigram"), (
+        f"model must be one of 'bpe', 'wordpiece' or 'unigram', not {model!r}"
+    )
+    _internal.train_tokenizer(
+        texts,
+        path,
+        model,
+        vocab_size,
+        min_frequency,
+        special_tokens or [],
+        unk_token,
+        lowercase,
+        strip_accents,
+        unicode_normalization,
+        pre_tokenizer,
     )
 
 
@@ -116,10 +365,20 @@ def uuid4(expr: IntoExprColumn) -> pl.Expr:
     )
 
 
+WORD_TOKENIZERS = ("unicode_words", "whitespace", "chars", "bytes")
+
+
+def _check_word_tokenizer(word_tokenizer: str | None) -> None:
+    assert word_tokenizer is None or word_tokenizer in WORD_TOKENIZERS, (
+        f"word_tokenizer must be one of {WORD_TOKENIZERS}, not {word_tokenizer!r}"
+    )
+
+
 def minhash(
     expr: IntoExprColumn,
     *,
     tokenizer_pattern: str = r"\w+",
+    word_tokenizer: str | None = None,
     seed=SEED,
     buckets=14,
     bsize=8,
@@ -128,6 +387,8 @@ def minhash(
     """
     construct a hex representation of the minhash hash of the given text column.
     `tokenizer_pattern`: tokenizer pattern for the word-shingling.
+    `word_tokenizer`: a built-in tokenizer used instead of `tokenizer_pattern`
+        ("unicode_words", "whitespace", "chars", or "bytes", see `WORD_TOKENIZERS`).
     `seed`: The seed for the hash-permutations.
     `buckets`: Number of minhash buckets.
     `bsize`: Size (in hashes) of each minhash bucket.
@@ -145,6 +406,7 @@ def minhash(
 
     Supplying buckets == 1 could be useful for varying bucket sizes.
     """
+    _check_word_tokenizer(word_tokenizer)
     return register_plugin_function(
         args=[expr],
         plugin_path=LIB,
@@ -152,6 +414,7 @@ def minhash(
         is_elementwise=True,
         kwargs={
             "tokenizer_pattern": tokenizer_pattern,
+            "word_tokenizer": word_tokenizer,
             "buckets": buckets,
             "bsize": bsize,
             "seed": seed,
@@ -161,14 +424,21 @@ def minhash(
 
 
 def repetition_signals(
-    expr: IntoExprColumn, *, tokenizer_pattern: str = r"\w+", num_top=4, num_dup=10
+    expr: IntoExprColumn,
+    *,
+    tokenizer_pattern: str = r"\w+",
+    word_tokenizer: str | None = None,
+    num_top=4,
+    num_dup=10,
 ) -> pl.Expr:
     """
     Runs gopher repetition signals on the given text column.
-    Words are extracted using the supplied tokenizer pattern.
+    Words are extracted using the supplied tokenizer pattern,
+    or the built-in `word_tokenizer` if given (see `WORD_TOKENIZERS`).
     Computes "top_n_gram_char_ratio"-signals for top_1, top_2, .. top_{num_top}.
     Computes "dup_n_gram_char_ratio"-signals for dup_{num_top+1}, .. dup_{num_dup}.
     """
+    _check_word_tokenizer(word_tokenizer)
     return register_plugin_function(
         args=[expr],
         plugin_path=LIB,
@@ -176,21 +446,422 @@ def repetition_signals(
         is_elementwise=True,
         kwargs={
             "tokenizer_pattern": tokenizer_pattern,
+            "word_tokenizer": word_tokenizer,
             "num_top": num_top,
             "num_dup": num_dup,
         },
     )
 
 
+PII_DETECTORS = (
+    "email",
+    "ipv4",
+    "ipv6",
+    "phone",
+    "credit_card",
+    "iban",
+    "ssn",
+    "api_key",
+)
+
+SECRET_RULES = (
+    "aws_access_key",
+    "aws_secret_key",
+    "github_token",
+    "gitlab_token",
+    "slack_token",
+    "slack_webhook",
+    "stripe_key",
+    "google_api_key",
+    "openai_key",
+    "private_key",
+    "jwt",
+    "connection_string_password",
+    "generic_secret",
+)
+
+
+def _scrub_rules(
+    patterns: List[str] | Dict[str, str] | None,
+    detectors: List[str] | None,
+    replacement: str | Dict[str, str] | None,
+    literals: List[str] | Dict[str, List[str]] | None = None,
+    secret_rules: List[str] | None = None,
+    case_insensitive: bool = False,
+    whole_word: bool = False,
+) -> List[dict]:
+    # (name, matcher, default replacement, priority)
+    rules = []
+    if isinstance(patterns, dict):
+        for name, pattern in patterns.items():
+            matcher = {"kind": "regex", "pattern": pattern}
+            rules.append((name, matcher, f"<{name}>", len(rules)))
+    else:
+        # Matches of a list of patterns are merged, so they share a priority,
+        # and a repeated pattern is the same rule.
+        for pattern in dict.fromkeys(patterns or []):
+            matcher = {"kind": "regex", "pattern": pattern}
+            rules.append((pattern, matcher, "REDACTED", 0))
+    unnamed = 0 if isinstance(patterns, dict) else len(rules)
+    priority = len(rules)
+    if literals is not None and not isinstance(literals, dict):
+        literals = {"LITERAL": literals}
+    for name, terms in (literals or {}).items():
+        matcher = {
+            "kind": "literals",
+            "terms": list(terms),
+            "case_insensitive": case_insensitive,
+            "whole_word": whole_word,
+        }
+        rules.append((name, matcher, f"<{name}>", priority))
+        priority += 1
+    for rule in secret_rules or []:
+        assert rule in SECRET_RULES, (
+            f"secret rule must be one of {SECRET_RULES}, not {rule!r}"
+        )
+        name = rule.upper()
+        matcher = {"kind": "secret", "rule": rule}
+        rules.append((name, matcher, f"<{name}>", priority))
+        priority += 1
+    for detector in detectors or []:
+        assert detector in PII_DETECTORS, (
+            f"detector must be one of {PII_DETECTORS}, not {detector!r}"
+        )
+        name = detector.upper()
+        matcher = {"kind": "pii", "detector": detector}
+        rules.append((name, matcher, f"<{name}>", priority))
+        priority += 1
+    # Rules are identified by name, e.g. in replacements and the counts of `find_spans`,
+    # so the names given by the user (rather than the patterns of a list) must be unique.
+    names = [name for name, *_ in rules[unnamed:]]
+    duplicates = sorted({name for name in names if names.count(name) > 1})
+    if duplicates:
+        raise ValueError(f"rule names must be unique, found duplicates: {duplicates}")
+
+    # Only per-pattern replacements of named patterns can refer to capture groups,
+    # so that replacements for all patterns (or for a list of patterns) are kept literally.
+    templates = (
+        set(replacement)
+        if isinstance(replacement, dict) and isinstance(patterns, dict)
+        else set()
+    )
+    if replacement is None:
+        replacement = {}
+    elif not isinstance(replacement, dict):
+        replacement = {name: replacement for name, *_ in rules}
+    return [
+        {
+            "name": name,
+            **matcher,
+            "replacement": replacement.get(name, default),
+            "priority": priority,
+            "template": name in templates and matcher["kind"] == "regex",
+        }
+        for name, matcher, default, priority in rules
+    ]
+
+
 def scrub(
-    expr: IntoExprColumn, *, patterns: List[str], replacement: str = "REDACTED"
+    expr: IntoExprColumn,
+    *,
+    patterns: List[str] | Dict[str, str] | None = None,
+    detectors: List[str] | None = None,
+    literals: List[str] | Dict[str, List[str]] | None = None,
+    secret_rules: List[str] | None = None,
+    case_insensitive: bool = False,
+    whole_word: bool = False,
+    replacement: str | Dict[str, str] | None = None,
+    mode: str = "replace",
+    secret: str | None = None,
 ) -> pl.Expr:
+    """
+    Replaces all matches of the given regex patterns, literal terms and PII detectors.
+    `patterns` is either a list of patterns, whose (merged) matches are replaced by `replacement`
+    (default "REDACTED"), or a dict of named patterns, e.g. `{"EMAIL": r"...", "IP": r"..."}`.
+    `detectors`: built-in PII detectors (see `PII_DETECTORS`), named by their upper case name,
+        e.g. "credit_card" => "CREDIT_CARD".
+    `literals`: a list of literal terms (named "LITERAL"), or a dict of named lists of terms,
+        matched with Aho-Corasick automata, which scales to tens of thousands of terms.
+        With `case_insensitive`, terms are matched ignoring (ASCII) case, and with `whole_word`,
+        only terms that are not part of a longer word are matched.
+    `secret_rules`: built-in secret rules (see `SECRET_RULES` and `detect_secrets`), named by their
+        upper case name, e.g. "github_token" => "GITHUB_TOKEN".
+    Matches of named patterns, literals, secrets and detectors are replaced by `<NAME>` (e.g. `<EMAIL>`), unless a
+    `replacement` is given, either for all patterns or as a dict of per-name replacements.
+    When matches overlap, named patterns win over literals, which win over secrets, which win
+    over detectors, and otherwise the one that comes first wins. The winner sets the replacement of
+    the whole overlapping text, so that no part of the other matches is left unredacted.
+    Per-pattern replacements of named patterns (a dict of `patterns` and a dict of `replacement`)
+    can refer to capture groups as `$name` or `${name}` (or `$1`),
+    e.g. `{"EMAIL": r"[a-z0-9._+-]+@(?P<domain>[a-z0-9.-]+)"}` with `{"EMAIL": "<USER>@$domain"}`
+    keeps the domain of emails. Use `$$` for a literal `$`. Merged matches are expanded with the
+    capture groups of their first match. A single `replacement` string is always used literally.
+
+    With `mode="pseudonymize"`, matches are instead replaced with deterministic surrogates derived
+    from a keyed hash (HMAC-SHA256) with the given `secret`, so that the same entity maps to the same
+    surrogate across the corpus. Emails become e.g. `user_3f2a9c0157d4e8b1@example.com`, IP addresses random
+    addresses of the same version, and other matches keep their format, with digits and letters
+    (including non-ASCII letters) replaced by random ASCII digits and letters.
+    """
+    assert mode in ("replace", "pseudonymize"), (
+        f"mode must be one of 'replace' or 'pseudonymize', not {mode!r}"
+    )
+    assert mode == "replace" or secret, "pseudonymize requires a secret"
     return register_plugin_function(
         args=[expr],
         plugin_path=LIB,
         function_name="scrub",
         is_elementwise=True,
-        kwargs={"patterns": patterns, "replacement": replacement},
+        kwargs={
+            "rules": _scrub_rules(
+                patterns,
+                detectors,
+                replacement,
+                literals,
+                secret_rules,
+                case_insensitive,
+                whole_word,
+            ),
+            "mode": mode,
+            "secret": secret,
+        },
+    )
+
+
+def find_spans(
+    expr: IntoExprColumn,
+    *,
+    patterns: List[str] | Dict[str, str] | None = None,
+    detectors: List[str] | None = None,
+    literals: List[str] | Dict[str, List[str]] | None = None,
+    secret_rules: List[str] | None = None,
+    case_insensitive: bool = False,
+    whole_word: bool = False,
+    overlapping: bool = False,
+) -> pl.Expr:
+    """
+    Finds the spans `scrub` would replace, for auditing redactions before rewriting.
+    `patterns`, `detectors`, `literals` and `secret_rules` are given as for `scrub`. Returns a struct with the fields
+    `spans`  : List(Struct{start, end, pattern}) = the byte offsets and pattern name of each span.
+    `counts` : Struct{<pattern>: UInt32}          = the number of spans per pattern.
+    `overlapping`: return all matches of all patterns, instead of the (merged) spans `scrub` replaces.
+    Patterns of a list are named by the pattern itself.
+    """
+    return register_plugin_function(
+        args=[expr],
+        plugin_path=LIB,
+        function_name="find_spans",
+        is_elementwise=True,
+        kwargs={
+            "rules": _scrub_rules(
+                patterns,
+                detectors,
+                None,
+                literals,
+                secret_rules,
+                case_insensitive,
+                whole_word,
+            ),
+            "overlapping": overlapping,
+        },
+    )
+
+
+def detect_secrets(
+    expr: IntoExprColumn,
+    *,
+    rules: List[str] | None = None,
+) -> pl.Expr:
+    """
+    Finds secrets and credentials (e.g. in code), using the given built-in `rules`
+    (see `SECRET_RULES`, all rules by default): AWS, GitHub, GitLab, Slack, Stripe, Google and
+    OpenAI keys and tokens, private key blocks, JWTs, passwords in connection strings,
+    and high-entropy values assigned to names like `password` or `api_key`.
+    Returns a list of `{start, end, rule}` structs with byte offsets and rule ids.
+    When matches overlap, the rule that comes first wins.
+    To remove secrets, pass the rules as `secret_rules` to `scrub`.
+    """
+    rules = list(SECRET_RULES if rules is None else rules)
+    for rule in rules:
+        assert rule in SECRET_RULES, (
+            f"rule must be one of {SECRET_RULES}, not {rule!r}"
+        )
+    return register_plugin_function(
+        args=[expr],
+        plugin_path=LIB,
+        function_name="detect_secrets",
+        is_elementwise=True,
+        kwargs={"rules": rules},
+    )
+
+
+def blocklist_signals(
+    expr: IntoExprColumn,
+    *,
+    lists: Dict[str, str] | str,
+    language: str | pl.Expr | None = None,
+    min_matches: int = 1,
+    min_ratio: float = 0.0,
+) -> pl.Expr:
+    """
+    Counts blocklisted (e.g. toxic or adult) words, using per-language word lists.
+    `lists`: the paths of the word lists by language, or a directory with one file per language,
+        named by the language (as in the LDNOOBW lists). Word lists have one word or phrase per line.
+    `language`: the language of all texts, or an expression with the language of each text
+        (rows whose language has no word list are null), which may also be a single value, e.g. `pl.lit("en")`.
+        If None, the words of all lists are matched, and overlapping matches are counted once.
+    Words are matched case insensitively and at word boundaries, where in scripts written without spaces
+    (e.g. Chinese, Japanese or Thai), words can start and end at any character.
+    Returns a struct with the fields `num_matches`, `num_words` (Unicode words), `match_ratio`
+    (`num_matches / num_words`, null without words), and `flagged`, which is true if there are
+    at least `min_matches` matches, and the match ratio is at least `min_ratio`.
+    """
+    assert min_matches >= 1, "min_matches must be at least 1"
+    if isinstance(lists, (str, os.PathLike)):
+        lists = {
+            path.name: str(path) for path in Path(lists).iterdir() if path.is_file()
+        }
+    args = [expr]
+    if isinstance(language, pl.Expr):
+        args.append(language)
+        language = None
+    return register_plugin_function(
+        args=args,
+        plugin_path=LIB,
+        function_name="blocklist_signals",
+        is_elementwise=True,
+        kwargs={
+            "lists": {lang: str(path) for lang, path in lists.items()},
+            "language": language,
+            "min_matches": min_matches,
+            "min_ratio": min_ratio,
+        },
+    )
+
+
+TRACKING_PARAMS = (
+    "utm_*",
+    "fbclid",
+    "gclid",
+    "dclid",
+    "gbraid",
+    "wbraid",
+    "msclkid",
+    "yclid",
+    "twclid",
+    "igshid",
+    "mc_cid",
+    "mc_eid",
+    "_ga",
+    "_gl",
+    "_hsenc",
+    "_hsmi",
+    "mkt_tok",
+)
+
+
+def extract_urls(expr: IntoExprColumn) -> pl.Expr:
+    """
+    Extracts all URLs (starting with `http://`, `https://`, `ftp://` or `www.`) from the text,
+    returned as a `List(String)`. Trailing punctuation is not considered part of a URL,
+    except for closing brackets balanced within the URL.
+    """
+    return register_plugin_function(
+        args=[expr],
+        plugin_path=LIB,
+        function_name="extract_urls",
+        is_elementwise=True,
+    )
+
+
+def parse_url(expr: IntoExprColumn) -> pl.Expr:
+    """
+    Parses URLs into a struct with the fields `scheme` (null for URLs without one, which are
+    parsed as http URLs), `host`, `domain` (the registered domain according to the embedded
+    Public Suffix List, e.g. "example.co.uk" for "www.example.co.uk"), `suffix` (the public
+    suffix, e.g. "co.uk"), `path` and `query`. Unparseable URLs are null.
+    """
+    return register_plugin_function(
+        args=[expr],
+        plugin_path=LIB,
+        function_name="parse_url",
+        is_elementwise=True,
+    )
+
+
+def normalize_url(
+    expr: IntoExprColumn,
+    *,
+    strip_params: List[str] = TRACKING_PARAMS,
+    keep_fragment: bool = False,
+    sort_query: bool = False,
+) -> pl.Expr:
+    """
+    Normalizes URLs for comparison and deduplication: the scheme and host are lowercased,
+    default ports and `.` and `..` path segments are removed, the query parameters in
+    `strip_params` are removed (default `TRACKING_PARAMS`, where a trailing `*` matches any suffix),
+    and so is the fragment, unless `keep_fragment`. With `sort_query`, the remaining query
+    parameters are sorted. Unparseable URLs are null.
+    """
+    return register_plugin_function(
+        args=[expr],
+        plugin_path=LIB,
+        function_name="normalize_url",
+        is_elementwise=True,
+        kwargs={
+            "strip_params": list(strip_params),
+            "keep_fragment": keep_fragment,
+            "sort_query": sort_query,
+        },
+    )
+
+
+def contains_any(
+    expr: IntoExprColumn,
+    *,
+    terms: List[str],
+    case_insensitive: bool = False,
+    whole_word: bool = False,
+) -> pl.Expr:
+    """
+    Returns whether each text contains any of the literal `terms`, using an Aho-Corasick automaton.
+    `case_insensitive`: ignore (ASCII) case.
+    `whole_word`: only match terms that are not part of a longer word.
+    """
+    return register_plugin_function(
+        args=[expr],
+        plugin_path=LIB,
+        function_name="contains_any",
+        is_elementwise=True,
+        kwargs={
+            "terms": list(terms),
+            "case_insensitive": case_insensitive,
+            "whole_word": whole_word,
+        },
+    )
+
+
+def count_matches(
+    expr: IntoExprColumn,
+    *,
+    terms: List[str],
+    case_insensitive: bool = False,
+    whole_word: bool = False,
+) -> pl.Expr:
+    """
+    Counts the (non-overlapping, leftmost longest) matches of the literal `terms` in each text,
+    returned as `UInt32`. The options are as for `contains_any`.
+    """
+    return register_plugin_function(
+        args=[expr],
+        plugin_path=LIB,
+        function_name="count_matches",
+        is_elementwise=True,
+        kwargs={
+            "terms": list(terms),
+            "case_insensitive": case_insensitive,
+            "whole_word": whole_word,
+        },
     )
 
 
@@ -201,6 +872,15 @@ def fasttext(
     labels: List[str],
     output_aggregate: bool = True,
     output_scores: bool = False,
+    split: str = "document",
+    chunk_size: int = 500,
+    whitespace: str = "collapse",
+    newlines: str = "space",
+    lowercase: bool = False,
+    split_punctuation: bool = False,
+    max_length: int | None = None,
+    on_error: str = "null",
+    n_threads: int = 1,
 ) -> pl.Expr:
     """
     Runs a fasttext model against the given text column.
@@ -214,7 +894,43 @@ def fasttext(
     output_scores=True =>
         output columns:
         `$label` : Float = score of the `$label` (only including given `labels`)
+    `split` controls what is classified:
+        "document" => the whole text is classified at once.
+        "lines"    => each non-blank line is classified separately.
+        "chunks"   => chunks of at most `chunk_size` characters are classified separately.
+    When splitting, the output columns above hold the length-weighted average over all chunks,
+    and an extra `chunks` column holds a list of per-chunk structs with the same fields,
+    along with the `start` and `end` byte offsets of each chunk.
+
+    The text is preprocessed before prediction, to match the normalization the model was trained with:
+    `whitespace`: "collapse" replaces every whitespace run with a single space, "keep" leaves it as is.
+    `newlines`: "space" treats newlines as whitespace, "eos" replaces them with the `</s>` token.
+    `lowercase`: lowercase the text.
+    `split_punctuation`: surround punctuation and other non-alphanumeric characters with spaces.
+    `max_length`: truncate the text (or each chunk) to at most `max_length` characters.
+
+    `on_error` controls what happens when fasttext fails to predict a text:
+        "null"         => the output is null, just as for null inputs.
+        "raise"        => an error is raised, reporting the row (within the batch) and the reason.
+        "error_column" => the output is null, and an extra `error` column holds the reason.
+
+    `n_threads` is the number of threads used to predict each batch (0 => all available cores).
+    The rows of each batch are split into contiguous ranges that are predicted concurrently,
+    sharing the same loaded model, on a thread pool shared by all batches.
     """
+    assert n_threads >= 0, f"n_threads must be non-negative, not {n_threads}"
+    assert split in ("document", "lines", "chunks"), (
+        f"split must be one of 'document', 'lines', or 'chunks', not {split!r}"
+    )
+    assert whitespace in ("collapse", "keep"), (
+        f"whitespace must be one of 'collapse' or 'keep', not {whitespace!r}"
+    )
+    assert newlines in ("space", "eos"), (
+        f"newlines must be one of 'space' or 'eos', not {newlines!r}"
+    )
+    assert on_error in ("null", "raise", "error_column"), (
+        f"on_error must be one of 'null', 'raise', or 'error_column', not {on_error!r}"
+    )
     return register_plugin_function(
         args=[expr],
         plugin_path=LIB,
@@ -225,10 +941,112 @@ def fasttext(
             "labels": labels,
             "output_aggregate": output_aggregate,
             "output_scores": output_scores,
+            "split": split,
+            "chunk_size": chunk_size,
+            "preprocessing": {
+                "whitespace": whitespace,
+                "newlines": newlines,
+                "lowercase": lowercase,
+                "split_punctuation": split_punctuation,
+                "max_length": max_length,
+            },
+            "on_error": on_error,
+            "n_threads": n_threads,
         },
     )
 
 
+def _model_path(path: str, kind: str) -> str:
+    if kind != "tokenizer":
+        return path
+    try:
+        return _tokenizer_path(path)
+    except ValueError:
+        # Let the registry report (or ignore) unknown paths.
+        return path
+
+
+def preload_model(path: str, *, kind: str, pin: bool = False) -> None:
+    """
+    Loads the model at `path` into the model registry shared by all expressions.
+    `kind` is the kind of model, either "fasttext" or "tokenizer".
+    Tokenizers can also be given by model name, as for `tokenize`.
+    `pin`: pinned models are never evicted by the `ttl` or `max_models` limits.
+    """
+    _internal.preload_model(kind, _model_path(path, kind), pin)
+
+
+def unpin_model(path: str, *, kind: str) -> bool:
+    """
+    Unpins a registered model, returning whether the model was found.
+    """
+    return _internal.unpin_model(kind, _model_path(path, kind))
+
+
+def evict_models(*, kind: str | None = None, path: str | None = None) -> int:
+    """
+    Evicts all registered models (including pinned ones) matching the given `kind` and `path`.
+    With no arguments, all models are evicted. Returns the number of evicted models.
+    """
+    if path is not None and kind is not None:
+        path = _model_path(path, kind)
+    return _internal.evict_models(kind, path)
+
+
+def list_models() -> List[dict]:
+    """
+    Lists the registered models, most recently used first, as dicts with the keys
+    `kind`, `path`, `pinned`, and `idle_seconds`.
+    """
+    return [
+        {"kind": kind, "path": path, "pinned": pinned, "idle_seconds": idle}
+        for kind, path, pinned, idle in _internal.list_models()
+    ]
+
+
+def set_model_cache(*, ttl: float | None = 60.0, max_models: int | None = None) -> None:
+    """
+    Configures when unpinned models are evicted from the registry.
+    `ttl`: evict models unused for more than `ttl` seconds (None => never, keeping them in memory until evicted).
+    `max_models`: keep at most `max_models` models, evicting the least recently used (None => unlimited).
+    Models loaded from a file are always reloaded when the file is modified.
+    The limits are applied whenever the registry is used (when a model is requested, pinned or
+    listed), so idle models are only freed then.
+    """
+    _internal.set_model_cache(ttl, max_models)
+
+
+def precompile_patterns(
+    *,
+    patterns: List[str] | Dict[str, str] | None = None,
+    detectors: List[str] | None = None,
+    literals: List[str] | Dict[str, List[str]] | None = None,
+    secret_rules: List[str] | None = None,
+    case_insensitive: bool = False,
+    whole_word: bool = False,
+    word_patterns: List[str] | None = None,
+) -> None:
+    """
+    Compiles patterns up front, raising a `ValueError` if any of them is invalid.
+    Compiled patterns are cached (separately from the models of the model registry),
+    so expressions using them don't have to compile them for their first batch.
+    `patterns`, `detectors`, `literals`, `secret_rules`, `case_insensitive` and `whole_word` are given
+    as for `scrub` and `find_spans`, and `word_patterns` are regexes used as the
+    `tokenizer_pattern` of `minhash` and `repetition_signals`,
+    or the `word_pattern` of `tokenizer_stats`.
+    """
+    rules = _scrub_rules(
+        patterns,
+        detectors,
+        None,
+        literals,
+        secret_rules,
+        case_insensitive,
+        whole_word,
+    )
+    _internal.precompile_patterns(json.dumps(rules), list(word_patterns or []))
+
+
 def register_namespace(name="textproc"):
     @pl.api.register_expr_namespace(name)
     class TextprocNamespace:
@@ -269,13 +1087,95 @@ def register_namespace(name="textproc"):
         def uuid4(self) -> pl.Expr:
             return uuid4(self._expr)
 
-        def tokenize(self, *, tokenizer: Tokenizer | str) -> pl.Expr:
-            return tokenize(self._expr, tokenizer=tokenizer)
+        def tokenize(
+            self,
+            *,
+            tokenizer: Tokenizer | str,
+            add_special_tokens: bool = False,
+            output_offsets: bool = False,
+            output_tokens: bool = False,
+            output_type_ids: bool = False,
+            output_attention_mask: bool = False,
+            max_length: int | None = None,
+            truncation: str = "right",
+            stride: int = 0,
+            overflow: bool = False,
+            padding: bool = False,
+            padding_side: str = "right",
+            pad_id: int | None = None,
+            batch_size: int = 1024,
+        ) -> pl.Expr:
+            return tokenize(
+                self._expr,
+                tokenizer=tokenizer,
+                add_special_tokens=add_special_tokens,
+                output_offsets=output_offsets,
+                output_tokens=output_tokens,
+                output_type_ids=output_type_ids,
+                output_attention_mask=output_attention_mask,
+                max_length=max_length,
+                truncation=truncation,
+                stride=stride,
+                overflow=overflow,
+                padding=padding,
+                padding_side=padding_side,
+                pad_id=pad_id,
+                batch_size=batch_size,
+            )
+
+        def token_count(
+            self, *, tokenizer: Tokenizer | str, add_special_tokens: bool = False
+        ) -> pl.Expr:
+            return token_count(
+                self._expr, tokenizer=tokenizer, add_special_tokens=add_special_tokens
+            )
+
+        def detokenize(
+            self, *, tokenizer: Tokenizer | str, skip_special_tokens: bool = False
+        ) -> pl.Expr:
+            return detokenize(
+                self._expr,
+                tokenizer=tokenizer,
+                skip_special_tokens=skip_special_tokens,
+            )
+
+        def tokenizer_stats(
+            self,
+            *,
+            tokenizer: Tokenizer | str,
+            word_pattern: str = r"\w+",
+            word_tokenizer: str | None = None,
+        ) -> pl.Expr:
+            return tokenizer_stats(
+                self._expr,
+                tokenizer=tokenizer,
+                word_pattern=word_pattern,
+                word_tokenizer=word_tokenizer,
+            )
+
+        def pack_sequences(
+            self,
+            *,
+            seq_len: int,
+            eos_id: int,
+            pad_id: int = 0,
+            strategy: str = "greedy",
+            drop_last: bool = False,
+        ) -> pl.Expr:
+            return pack_sequences(
+                self._expr,
+                seq_len=seq_len,
+                eos_id=eos_id,
+                pad_id=pad_id,
+                strategy=strategy,
+                drop_last=drop_last,
+            )
 
         def minhash(
             self,
             *,
             tokenizer_pattern: str = r"\w+",
+            word_tokenizer: str | None = None,
             seed=SEED,
             buckets=14,
             bsize=8,
@@ -284,6 +1184,7 @@ def register_namespace(name="textproc"):
             return minhash(
                 self._expr,
                 tokenizer_pattern=tokenizer_pattern,
+                word_tokenizer=word_tokenizer,
                 seed=seed,
                 buckets=buckets,
                 bsize=bsize,
@@ -294,12 +1195,14 @@ def register_namespace(name="textproc"):
             self,
             *,
             tokenizer_pattern: str = r"\w+",
+            word_tokenizer: str | None = None,
             num_top=4,
             num_dup=10,
         ) -> pl.Expr:
             return repetition_signals(
                 self._expr,
                 tokenizer_pattern=tokenizer_pattern,
+                word_tokenizer=word_tokenizer,
                 num_top=num_top,
                 num_dup=num_dup,
             )
@@ -307,10 +1210,117 @@ def register_namespace(name="textproc"):
         def scrub(
             self,
             *,
-            patterns: List[str],
-            replacement: str = "REDACTED",
+            patterns: List[str] | Dict[str, str] | None = None,
+            detectors: List[str] | None = None,
+            literals: List[str] | Dict[str, List[str]] | None = None,
+            secret_rules: List[str] | None = None,
+            case_insensitive: bool = False,
+            whole_word: bool = False,
+            replacement: str | Dict[str, str] | None = None,
+            mode: str = "replace",
+            secret: str | None = None,
+        ) -> pl.Expr:
+            return scrub(
+                self._expr,
+                patterns=patterns,
+                detectors=detectors,
+                literals=literals,
+                secret_rules=secret_rules,
+                case_insensitive=case_insensitive,
+                whole_word=whole_word,
+                replacement=replacement,
+                mode=mode,
+                secret=secret,
+            )
+
+        def find_spans(
+            self,
+            *,
+            patterns: List[str] | Dict[str, str] | None = None,
+            detectors: List[str] | None = None,
+            literals: List[str] | Dict[str, List[str]] | None = None,
+            secret_rules: List[str] | None = None,
+            case_insensitive: bool = False,
+            whole_word: bool = False,
+            overlapping: bool = False,
+        ) -> pl.Expr:
+            return find_spans(
+                self._expr,
+                patterns=patterns,
+                detectors=detectors,
+                literals=literals,
+                secret_rules=secret_rules,
+                case_insensitive=case_insensitive,
+                whole_word=whole_word,
+                overlapping=overlapping,
+            )
+
+        def detect_secrets(self, *, rules: List[str] | None = None) -> pl.Expr:
+            return detect_secrets(self._expr, rules=rules)
+
+        def blocklist_signals(
+            self,
+            *,
+            lists: Dict[str, str] | str,
+            language: str | pl.Expr | None = None,
+            min_matches: int = 1,
+            min_ratio: float = 0.0,
+        ) -> pl.Expr:
+            return blocklist_signals(
+                self._expr,
+                lists=lists,
+                language=language,
+                min_matches=min_matches,
+                min_ratio=min_ratio,
+            )
+
+        def extract_urls(self) -> pl.Expr:
+            return extract_urls(self._expr)
+
+        def parse_url(self) -> pl.Expr:
+            return parse_url(self._expr)
+
+        def normalize_url(
+            self,
+            *,
+            strip_params: List[str] = TRACKING_PARAMS,
+            keep_fragment: bool = False,
+            sort_query: bool = False,
         ) -> pl.Expr:
-            return scrub(self._expr, patterns=patterns, replacement=replacement)
+            return normalize_url(
+                self._expr,
+                strip_params=strip_params,
+                keep_fragment=keep_fragment,
+                sort_query=sort_query,
+            )
+
+        def contains_any(
+            self,
+            *,
+            terms: List[str],
+            case_insensitive: bool = False,
+            whole_word: bool = False,
+        ) -> pl.Expr:
+            return contains_any(
+                self._expr,
+                terms=terms,
+                case_insensitive=case_insensitive,
+                whole_word=whole_word,
+            )
+
+        def count_matches(
+            self,
+            *,
+            terms: List[str],
+            case_insensitive: bool = False,
+            whole_word: bool = False,
+        ) -> pl.Expr:
+            return count_matches(
+                self._expr,
+                terms=terms,
+                case_insensitive=case_insensitive,
+                whole_word=whole_word,
+            )
 
         def fasttext(
             self,
@@ -319,6 +1329,15 @@ def register_namespace(name="textproc"):
             labels: List[str],
             output_aggregate: bool = True,
             output_scores: bool = False,
+            split: str = "document",
+            chunk_size: int = 500,
+            whitespace: str = "collapse",
+            newlines: str = "space",
+            lowercase: bool = False,
+            split_punctuation: bool = False,
+            max_length: int | None = None,
+            on_error: str = "null",
+            n_threads: int = 1,
         ) -> pl.Expr:
             return fasttext(
                 self._expr,
@@ -326,4 +1345,13 @@ def register_namespace(name="textproc"):
                 labels=labels,
                 output_aggregate=output_aggregate,
                 output_scores=output_scores,
+                split=split,
+                chunk_size=chunk_size,
+                whitespace=whitespace,
+                newlines=newlines,
+                lowercase=lowercase,
+                split_punctuation=split_punctuation,
+                max_length=max_length,
+                on_error=on_error,
+                n_threads=n_threads,
             )
@@ -1 +1,23 @@
+from polars import Series
+
 __version__: str
+
+def preload_model(kind: str, path: str, pin: bool = False) -> None: ...
+def unpin_model(kind: str, path: str) -> bool: ...
+def evict_models(kind: str | None = None, path: str | None = None) -> int: ...
+def list_models() -> list[tuple[str, str, bool, float]]: ...
+def set_model_cache(ttl: float | None = None, max_models: int | None = None) -> None: ...
+def precompile_patterns(rules: str, word_patterns: list[str]) -> None: ...
+def train_tokenizer(
+    texts: Series,
+    path: str,
+    model: str,
+    vocab_size: int,
+    min_frequency: int,
+    special_tokens: list[str],
+    unk_token: str | None,
+    lowercase: bool,
+    strip_accents: bool,
+    unicode_normalization: str | None,
+    pre_tokenizer: str | None,
+) -> None: ...
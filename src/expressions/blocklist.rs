@@ -0,0 +1,140 @@
+//! Flagging of documents containing blocklisted (e.g. toxic or adult) words,
+//! using per-language word lists such as the LDNOOBW lists used for C4.
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use polars::prelude::*;
+use pyo3_polars::derive::polars_expr;
+use serde::Deserialize;
+use unicode_segmentation::UnicodeSegmentation;
+
+use super::literals::{leftmost_longest, LiteralMatcher};
+use crate::registry;
+
+/// Loads a word list with one word (or phrase) per line, matched case insensitively
+/// as whole words.
+fn load_blocklist(path: &str) -> Result<Arc<LiteralMatcher>, String> {
+    registry::load_pattern_file("blocklist", path, |path| {
+        let words = std::fs::read_to_string(path)
+            .map_err(|e| format!("Error loading blocklist {}: {}", path, e))?;
+        let words: Vec<String> = words
+            .lines()
+            .map(|word| word.trim().to_lowercase())
+            .filter(|word| !word.is_empty())
+            .collect();
+        LiteralMatcher::from_terms(&words, false, true).map_err(|e| e.to_string())
+    })
+}
+
+#[derive(Deserialize)]
+struct BlocklistKwargs {
+    /// The paths of the word lists by language.
+    lists: HashMap<String, String>,
+    /// The language of all texts. Without it, and without a language input,
+    /// the words of all lists are matched.
+    language: Option<String>,
+    min_matches: u32,
+    min_ratio: f32,
+}
+
+fn blocklist_signals_output(input_fields: &[Field]) -> PolarsResult<Field> {
+    let field = &input_fields[0];
+    match field.dtype() {
+        DataType::String => {
+            let fields = vec![
+                Field::new("num_matches".into(), DataType::UInt32),
+                Field::new("num_words".into(), DataType::UInt32),
+                Field::new("match_ratio".into(), DataType::Float32),
+                Field::new("flagged".into(), DataType::Boolean),
+            ];
+            Ok(Field::new(field.name.clone(), DataType::Struct(fields)))
+        },
+        dtype => polars_bail!(InvalidOperation: "expected string dtype, got {}", dtype),
+    }
+}
+
+/// Counts the blocklisted words in each text, using the word list of its language, which is
+/// either fixed or given per row by a second (string) input. Rows whose language has no word
+/// list are null. Texts are lowercased, and words only match at word boundaries, where any
+/// character boundary in scripts without spaces (e.g. Chinese or Thai) counts as one.
+#[polars_expr(output_type_func = blocklist_signals_output)]
+fn blocklist_signals(inputs: &[Series], kwargs: BlocklistKwargs) -> PolarsResult<Series> {
+    let ca: &StringChunked = inputs[0].str()?;
+    let blocklists: HashMap<&str, Arc<LiteralMatcher>> = kwargs
+        .lists
+        .iter()
+        .filter(|(language, _)| kwargs.language.as_ref().is_none_or(|l| l == *language))
+        .map(|(language, path)| Ok((language.as_str(), load_blocklist(path)?)))
+        .collect::<Result<_, String>>()
+        .map_err(|e| polars_err!(ComputeError: "{}", e))?;
+    if let Some(language) = &kwargs.language {
+        polars_ensure!(
+            blocklists.contains_key(language.as_str()),
+            ComputeError: "no blocklist for language {}", language
+        );
+    }
+    let all: Vec<&LiteralMatcher> = blocklists.values().map(|m| m.as_ref()).collect();
+    let languages = match inputs.get(1) {
+        Some(languages) => {
+            let languages = languages.str()?;
+            polars_ensure!(
+                languages.len() == ca.len() || languages.len() == 1,
+                ShapeMismatch: "expected {} languages (or a single one), got {}", ca.len(), languages.len()
+            );
+            Some(languages)
+        },
+        None => None,
+    };
+
+    let mut num_matches: Vec<Option<u32>> = Vec::with_capacity(ca.len());
+    let mut num_words: Vec<Option<u32>> = Vec::with_capacity(ca.len());
+    let mut match_ratio: Vec<Option<f32>> = Vec::with_capacity(ca.len());
+    let mut flagged: Vec<Option<bool>> = Vec::with_capacity(ca.len());
+
+    for (i, opt) in ca.iter().enumerate() {
+        let matchers = match languages {
+            // A single language (e.g. a literal) applies to all rows.
+            Some(languages) => languages
+                .get(if languages.len() == 1 { 0 } else { i })
+                .and_then(|language| blocklists.get(language))
+                .map(|matcher| vec![matcher.as_ref()]),
+            None => Some(all.clone()),
+        };
+        let (Some(text), Some(matchers)) = (opt, matchers) else {
+            num_matches.push(None);
+            num_words.push(None);
+            match_ratio.push(None);
+            flagged.push(None);
+            continue;
+        };
+        let text = text.to_lowercase();
+        let mut matches: Vec<(usize, usize)> = matchers
+            .iter()
+            .flat_map(|matcher| matcher.find_iter(&text))
+            .collect();
+        if matchers.len() > 1 {
+            // The same word, or overlapping words (e.g. "ass" and "asshole"), may be on several
+            // lists, so that their matches overlap.
+            leftmost_longest(&mut matches);
+        }
+        let matches = matches.len();
+        let words = text.unicode_words().count();
+        let ratio = (words > 0).then(|| ((matches as f64) / (words as f64)) as f32);
+        num_matches.push(Some(matches as u32));
+        num_words.push(Some(words as u32));
+        match_ratio.push(ratio);
+        flagged.push(Some(
+            matches as u32 >= kwargs.min_matches && ratio.unwrap_or(0.0) >= kwargs.min_ratio,
+        ));
+    }
+
+    let fields = [
+        UInt32Chunked::from_iter_options("num_matches".into(), num_matches.into_iter())
+            .into_series(),
+        UInt32Chunked::from_iter_options("num_words".into(), num_words.into_iter()).into_series(),
+        Float32Chunked::from_iter_options("match_ratio".into(), match_ratio.into_iter())
+            .into_series(),
+        BooleanChunked::from_iter_options("flagged".into(), flagged.into_iter()).into_series(),
+    ];
+    StructChunked::from_series(ca.name().clone(), ca.len(), fields.iter()).map(|x| x.into_series())
+}
@@ -1,21 +1,23 @@
 use std::collections::HashMap;
 use std::io::Error;
 use std::sync::Arc;
-use std::time::Duration;
 
-use cached::proc_macro::cached;
 use fasttext::FastText;
 use polars::prelude::*;
 use polars_arrow::bitmap::{Bitmap, MutableBitmap};
 use pyo3_polars::derive::polars_expr;
-use regex::Regex;
+use rayon::prelude::*;
 use serde::Deserialize;
 
-#[cached(time = 60, time_refresh = true, sync_writes = "by_key")]
-fn load_model(path: String) -> Result<Arc<FastText>, String> {
-    let mut model = FastText::new();
-    model.load_model(&path)?;
-    Ok(Arc::new(model))
+use super::utils::list_from_offsets;
+use crate::registry;
+
+pub(crate) fn load_model(path: &str) -> Result<Arc<FastText>, String> {
+    registry::load_file("fasttext", path, |path| {
+        let mut model = FastText::new();
+        model.load_model(path)?;
+        Ok(model)
+    })
 }
 
 struct FasttextModel {
@@ -30,9 +32,31 @@ struct FasttextOutput {
     scores: Vec<f32>,
 }
 
+impl FasttextOutput {
+    fn from_scores(scores: Vec<f32>) -> Self {
+        let mut top_label = 0;
+        let mut top_score = 0.0;
+        let mut total_score = 0.0;
+
+        scores.iter().enumerate().for_each(|(i, &score)| {
+            total_score += score;
+            if score > top_score {
+                top_label = i as u32;
+                top_score = score;
+            }
+        });
+        FasttextOutput {
+            top_label,
+            top_score,
+            total_score,
+            scores,
+        }
+    }
+}
+
 impl FasttextModel {
     fn new(path: &str, labels: &[String]) -> Result<Self, String> {
-        let m = load_model(path.into())?;
+        let m = load_model(path)?;
         Ok(Self {
             model: m,
             labelmap: HashMap::from_iter(labels.iter().enumerate().map(|(i, s)| (s.clone(), i))),
@@ -46,33 +70,268 @@ impl FasttextModel {
     fn predict(&self, txt: &str) -> Result<FasttextOutput, String> {
         let preds = self.model.predict(txt, -1, 0.0)?;
         let mut scores: Vec<f32> = vec![0.0; self.len()];
-        let mut top_label = 0;
-        let mut top_score = 0.0;
-        let mut total_score = 0.0;
 
         preds.into_iter().for_each(|p| {
             if let Some(i) = self.labelmap.get(&p.label) {
-                let i = *i;
-                scores[i] = p.prob;
-                total_score += p.prob;
-                if p.prob > top_score {
-                    top_label = i as u32;
-                    top_score = p.prob;
-                }
+                scores[*i] = p.prob;
             }
         });
-        Ok(FasttextOutput {
-            top_label,
-            top_score,
-            total_score,
-            scores,
-        })
+        Ok(FasttextOutput::from_scores(scores))
     }
 }
 
-fn fasttext_output(input_fields: &[Field], kwargs: FasttextKwargs) -> PolarsResult<Field> {
-    let field = &input_fields[0];
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Split {
+    Document,
+    Lines,
+    Chunks,
+}
+
+/// Byte bounds of the non-blank lines of `txt`, excluding the newlines.
+fn line_bounds(txt: &str) -> Vec<(usize, usize)> {
+    let mut bounds = Vec::new();
+    let mut start = 0;
+    for line in txt.split('\n') {
+        let end = start + line.len();
+        if !line.trim().is_empty() {
+            bounds.push((start, end));
+        }
+        start = end + 1;
+    }
+    bounds
+}
+
+/// Byte bounds of consecutive chunks of at most `size` characters.
+/// Chunks are cut at the last whitespace in the window (if any), to avoid splitting words.
+fn chunk_bounds(txt: &str, size: usize) -> Vec<(usize, usize)> {
+    let mut bounds = Vec::new();
+    let mut start = 0;
+    while start < txt.len() {
+        let rest = &txt[start..];
+        let end = match rest.char_indices().nth(size) {
+            None => txt.len(),
+            Some((i, _)) => match rest[..i].rfind(char::is_whitespace) {
+                Some(j) if j > 0 => start + j,
+                _ => start + i,
+            },
+        };
+        if !txt[start..end].trim().is_empty() {
+            bounds.push((start, end));
+        }
+        start = end;
+    }
+    bounds
+}
+
+/// What to do when fasttext fails to predict a row.
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum OnError {
+    /// Output null, just as for null inputs.
+    Null,
+    /// Fail the whole expression, reporting the row and the error.
+    Raise,
+    /// Output null, and report the error in an additional `error` field.
+    ErrorColumn,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Whitespace {
+    Collapse,
+    Keep,
+}
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Newlines {
+    Space,
+    Eos,
+}
+
+/// Text normalization applied before prediction, to match the normalization used when training
+/// the model. Note that fasttext only predicts on the first line of its input, so newlines are
+/// always replaced, either by whitespace or by the `</s>` end-of-sentence token.
+#[derive(Deserialize)]
+struct Preprocessing {
+    whitespace: Whitespace,
+    newlines: Newlines,
+    lowercase: bool,
+    split_punctuation: bool,
+    max_length: Option<usize>,
+}
 
+impl Preprocessing {
+    fn push_space(&self, out: &mut String, in_space: &mut bool, c: char) {
+        match self.whitespace {
+            Whitespace::Collapse => {
+                if !*in_space {
+                    out.push(' ');
+                }
+            },
+            Whitespace::Keep => out.push(c),
+        }
+        *in_space = true;
+    }
+
+    /// Writes the preprocessed `txt` into `out`, replacing its previous contents.
+    fn apply(&self, txt: &str, out: &mut String) {
+        out.clear();
+        let txt = match self.max_length.and_then(|n| txt.char_indices().nth(n)) {
+            Some((i, _)) => &txt[..i],
+            None => txt,
+        };
+
+        let mut in_space = false;
+        for c in txt.chars() {
+            if c == '\n' {
+                self.push_space(out, &mut in_space, ' ');
+                if self.newlines == Newlines::Eos {
+                    out.push_str("</s>");
+                    in_space = false;
+                    self.push_space(out, &mut in_space, ' ');
+                }
+            } else if c.is_whitespace() {
+                self.push_space(out, &mut in_space, c);
+            } else if self.split_punctuation && !c.is_alphanumeric() {
+                self.push_space(out, &mut in_space, ' ');
+                out.push(c);
+                in_space = false;
+                self.push_space(out, &mut in_space, ' ');
+            } else {
+                if self.lowercase {
+                    out.extend(c.to_lowercase());
+                } else {
+                    out.push(c);
+                }
+                in_space = false;
+            }
+        }
+    }
+}
+
+/// Column-wise accumulator of fasttext outputs, matching the fields of `prediction_fields`.
+struct PredictionColumns {
+    output_aggregate: bool,
+    output_scores: bool,
+    top_label: Vec<u32>,
+    top_score: Vec<f32>,
+    total_score: Vec<f32>,
+    label_scores: Vec<Vec<f32>>,
+    validities: MutableBitmap,
+}
+
+impl PredictionColumns {
+    fn new(kwargs: &FasttextKwargs, capacity: usize) -> Self {
+        let mut columns = Self {
+            output_aggregate: kwargs.output_aggregate,
+            output_scores: kwargs.output_scores,
+            top_label: Vec::new(),
+            top_score: Vec::new(),
+            total_score: Vec::new(),
+            label_scores: Vec::new(),
+            validities: MutableBitmap::with_capacity(capacity),
+        };
+
+        if columns.output_aggregate {
+            columns.top_label.reserve_exact(capacity);
+            columns.top_score.reserve_exact(capacity);
+            columns.total_score.reserve_exact(capacity);
+        }
+
+        if columns.output_scores {
+            for _ in 0..kwargs.labels.len() {
+                columns.label_scores.push(Vec::with_capacity(capacity));
+            }
+        }
+        columns
+    }
+
+    fn push(&mut self, output: Option<FasttextOutput>) {
+        match output {
+            Some(output) => {
+                self.validities.push(true);
+                if self.output_aggregate {
+                    self.top_label.push(output.top_label);
+                    self.top_score.push(output.top_score);
+                    self.total_score.push(output.total_score);
+                }
+                if self.output_scores {
+                    self.label_scores
+                        .iter_mut()
+                        .zip(output.scores)
+                        .for_each(|(r, s)| {
+                            r.push(s);
+                        });
+                }
+            },
+            None => {
+                self.validities.push(false);
+                if self.output_aggregate {
+                    self.top_label.push(0);
+                    self.top_score.push(0.0);
+                    self.total_score.push(0.0);
+                }
+                if self.output_scores {
+                    self.label_scores.iter_mut().for_each(|r| {
+                        r.push(0.0);
+                    });
+                }
+            },
+        }
+    }
+
+    fn into_series(self, labels: &[String]) -> Vec<Series> {
+        let validities: Bitmap = self.validities.into();
+        let mut res: Vec<Series> = Vec::new();
+
+        if self.output_aggregate {
+            res.push(
+                ChunkedArray::<UInt32Type>::from_vec_validity(
+                    "top_label".into(),
+                    self.top_label,
+                    Some(validities.clone()),
+                )
+                .apply_into_string_amortized(|index: u32, output: &mut String| {
+                    output.push_str(&labels[index as usize]);
+                })
+                .into_series(),
+            );
+            res.push(
+                ChunkedArray::<Float32Type>::from_vec_validity(
+                    "top_score".into(),
+                    self.top_score,
+                    Some(validities.clone()),
+                )
+                .into_series(),
+            );
+            res.push(
+                ChunkedArray::<Float32Type>::from_vec_validity(
+                    "total_score".into(),
+                    self.total_score,
+                    Some(validities.clone()),
+                )
+                .into_series(),
+            );
+        }
+        if self.output_scores {
+            for (i, label_score) in self.label_scores.into_iter().enumerate() {
+                res.push(
+                    ChunkedArray::<Float32Type>::from_vec_validity(
+                        labels[i].clone().into(),
+                        label_score,
+                        Some(validities.clone()),
+                    )
+                    .into_series(),
+                )
+            }
+        }
+        res
+    }
+}
+
+fn prediction_fields(kwargs: &FasttextKwargs) -> Vec<Field> {
     let mut fields = Vec::new();
 
     if kwargs.output_aggregate {
@@ -81,10 +340,36 @@ fn fasttext_output(input_fields: &[Field], kwargs: FasttextKwargs) -> PolarsResu
         fields.push(Field::new("total_score".into(), DataType::Float32));
     }
     if kwargs.output_scores {
-        for label in kwargs.labels {
+        for label in &kwargs.labels {
             fields.push(Field::new(label.into(), DataType::Float32));
         }
     }
+    fields
+}
+
+fn chunk_fields(kwargs: &FasttextKwargs) -> Vec<Field> {
+    let mut fields = vec![
+        Field::new("start".into(), DataType::UInt32),
+        Field::new("end".into(), DataType::UInt32),
+    ];
+    fields.extend(prediction_fields(kwargs));
+    fields
+}
+
+fn fasttext_output(input_fields: &[Field], kwargs: FasttextKwargs) -> PolarsResult<Field> {
+    let field = &input_fields[0];
+
+    let mut fields = prediction_fields(&kwargs);
+    if kwargs.split != Split::Document {
+        fields.push(Field::new(
+            "chunks".into(),
+            DataType::List(Box::new(DataType::Struct(chunk_fields(&kwargs)))),
+        ));
+    }
+
+    if kwargs.on_error == OnError::ErrorColumn {
+        fields.push(Field::new("error".into(), DataType::String));
+    }
 
     match field.dtype() {
         DataType::String => Ok(Field::new("langid".into(), DataType::Struct(fields))),
@@ -98,121 +383,186 @@ struct FasttextKwargs {
     labels: Vec<String>,
     output_aggregate: bool,
     output_scores: bool,
+    split: Split,
+    chunk_size: usize,
+    preprocessing: Preprocessing,
+    on_error: OnError,
+    n_threads: usize,
 }
 
 impl FasttextKwargs {
     fn load(&self) -> Result<FasttextModel, Error> {
         FasttextModel::new(&self.path, &self.labels).map_err(std::io::Error::other)
     }
+
+    fn bounds(&self, txt: &str) -> Vec<(usize, usize)> {
+        match self.split {
+            Split::Document => vec![(0, txt.len())],
+            Split::Lines => line_bounds(txt),
+            Split::Chunks => chunk_bounds(txt, self.chunk_size),
+        }
+    }
 }
 
-#[polars_expr(output_type_func_with_kwargs = fasttext_output)]
-fn fasttext(inputs: &[Series], kwargs: FasttextKwargs) -> PolarsResult<Series> {
-    let ca: &StringChunked = inputs[0].str()?;
-    let model = kwargs.load()?;
+/// Predicts the rows of `ca`, returning the output struct.
+/// `offset` is the position of `ca` in the batch, used when reporting errors.
+fn predict_rows(
+    model: &FasttextModel,
+    kwargs: &FasttextKwargs,
+    ca: &StringChunked,
+    offset: usize,
+) -> PolarsResult<Series> {
     let l = ca.len();
     let n = model.len();
 
-    let mut validities = MutableBitmap::with_capacity(l);
-    validities.extend_constant(ca.len(), true);
+    let mut predictions = PredictionColumns::new(kwargs, l);
 
-    let mut top_label: Vec<u32> = Vec::new();
-    let mut top_score: Vec<f32> = Vec::new();
-    let mut total_score: Vec<f32> = Vec::new();
-    let mut label_scores: Vec<Vec<f32>> = Vec::new();
+    let mut chunk_predictions = PredictionColumns::new(kwargs, 0);
+    let mut chunk_starts: Vec<u32> = Vec::new();
+    let mut chunk_ends: Vec<u32> = Vec::new();
+    let mut chunk_offsets: Vec<i64> = Vec::with_capacity(l + 1);
+    let mut chunk_validities = MutableBitmap::with_capacity(l);
+    chunk_offsets.push(0);
 
-    if kwargs.output_aggregate {
-        top_label.reserve_exact(l);
-        top_score.reserve_exact(l);
-        total_score.reserve_exact(l);
-    }
+    let mut buf = String::new();
 
-    if kwargs.output_scores {
-        for _ in 0..n {
-            label_scores.push(Vec::with_capacity(l));
-        }
-    }
+    let mut errors: Vec<Option<String>> = Vec::new();
 
-    let space_pattern = Regex::new(r"\s+").unwrap();
-
-    ca.iter().enumerate().for_each(|(row, v)| {
-        match v.and_then(|txt| model.predict(&space_pattern.replace_all(txt, " ")).ok()) {
-            Some(output) => {
-                if kwargs.output_aggregate {
-                    top_label.push(output.top_label);
-                    top_score.push(output.top_score);
-                    total_score.push(output.total_score);
+    for (row, v) in ca.iter().enumerate() {
+        // The first prediction error of the row, if any.
+        let mut error: Option<String> = None;
+        match kwargs.split {
+            Split::Document => {
+                let output = v.and_then(|txt| {
+                    kwargs.preprocessing.apply(txt, &mut buf);
+                    match model.predict(&buf) {
+                        Ok(output) => Some(output),
+                        Err(e) => {
+                            error = Some(e);
+                            None
+                        },
+                    }
+                });
+                predictions.push(output);
+            },
+            _ => {
+                // Length-weighted average of the chunk scores.
+                let mut weighted: Vec<f32> = vec![0.0; n];
+                let mut total_weight = 0.0;
+                if let Some(txt) = v {
+                    for (start, end) in kwargs.bounds(txt) {
+                        let chunk = &txt[start..end];
+                        kwargs.preprocessing.apply(chunk, &mut buf);
+                        let output = match model.predict(&buf) {
+                            Ok(output) => Some(output),
+                            Err(e) => {
+                                error.get_or_insert(e);
+                                None
+                            },
+                        };
+                        if let Some(output) = &output {
+                            let weight = chunk.chars().count() as f32;
+                            weighted
+                                .iter_mut()
+                                .zip(&output.scores)
+                                .for_each(|(w, s)| *w += weight * s);
+                            total_weight += weight;
+                        }
+                        chunk_starts.push(start as u32);
+                        chunk_ends.push(end as u32);
+                        chunk_predictions.push(output);
+                    }
                 }
-                if kwargs.output_scores {
-                    label_scores
-                        .iter_mut()
-                        .zip(output.scores)
-                        .for_each(|(r, s)| {
-                            r.push(s);
-                        });
+                chunk_validities.push(v.is_some());
+                chunk_offsets.push(chunk_starts.len() as i64);
+
+                if total_weight > 0.0 {
+                    weighted.iter_mut().for_each(|w| *w /= total_weight);
+                    predictions.push(Some(FasttextOutput::from_scores(weighted)));
+                } else {
+                    predictions.push(None);
                 }
             },
-            None => {
-                validities.set(row, false);
-                if kwargs.output_aggregate {
-                    top_label.push(0);
-                    top_score.push(0.0);
-                    total_score.push(0.0);
-                }
-                if kwargs.output_scores {
-                    label_scores.iter_mut().for_each(|r| {
-                        r.push(0.0);
-                    });
+        }
+
+        match kwargs.on_error {
+            OnError::Null => {},
+            OnError::Raise => {
+                if let Some(e) = error {
+                    polars_bail!(ComputeError: "fasttext prediction failed for row {}: {}", offset + row, e)
                 }
             },
+            OnError::ErrorColumn => errors.push(error),
         }
-    });
+    }
 
-    let validities: Bitmap = validities.into();
-    let mut res: Vec<Series> = Vec::new();
+    let mut res = predictions.into_series(&kwargs.labels);
 
-    if kwargs.output_aggregate {
-        res.push(
-            ChunkedArray::<UInt32Type>::from_vec_validity(
-                "top_label".into(),
-                top_label,
-                Some(validities.clone()),
-            )
-            .apply_into_string_amortized(|index: u32, output: &mut String| {
-                output.push_str(&kwargs.labels[index as usize]);
-            })
-            .into_series(),
-        );
-        res.push(
-            ChunkedArray::<Float32Type>::from_vec_validity(
-                "top_score".into(),
-                top_score,
-                Some(validities.clone()),
-            )
-            .into_series(),
-        );
+    if kwargs.split != Split::Document {
+        let num_chunks = chunk_starts.len();
+        let mut chunk_fields = vec![
+            UInt32Chunked::from_vec("start".into(), chunk_starts).into_series(),
+            UInt32Chunked::from_vec("end".into(), chunk_ends).into_series(),
+        ];
+        chunk_fields.extend(chunk_predictions.into_series(&kwargs.labels));
+        let chunks = StructChunked::from_series("chunks".into(), num_chunks, chunk_fields.iter())?;
+        res.push(list_from_offsets(
+            "chunks".into(),
+            chunks.into_series(),
+            chunk_offsets,
+            Some(chunk_validities.into()),
+        )?);
+    }
+
+    if kwargs.on_error == OnError::ErrorColumn {
         res.push(
-            ChunkedArray::<Float32Type>::from_vec_validity(
-                "total_score".into(),
-                total_score,
-                Some(validities.clone()),
-            )
-            .into_series(),
+            StringChunked::from_iter_options("error".into(), errors.into_iter()).into_series(),
         );
     }
-    if kwargs.output_scores {
-        for (i, label_score) in label_scores.into_iter().enumerate() {
-            res.push(
-                ChunkedArray::<Float32Type>::from_vec_validity(
-                    kwargs.labels[i].clone().into(),
-                    label_score,
-                    Some(validities.clone()),
-                )
-                .into_series(),
-            )
-        }
+
+    StructChunked::from_series(ca.name().clone(), l, res.iter()).map(|x| x.into_series())
+}
+
+#[polars_expr(output_type_func_with_kwargs = fasttext_output)]
+fn fasttext(inputs: &[Series], kwargs: FasttextKwargs) -> PolarsResult<Series> {
+    let ca: &StringChunked = inputs[0].str()?;
+    if kwargs.split == Split::Chunks && kwargs.chunk_size == 0 {
+        polars_bail!(InvalidOperation: "chunk_size must be positive")
+    }
+    let model = kwargs.load()?;
+    let l = ca.len();
+
+    let n_threads = match kwargs.n_threads {
+        0 => rayon::current_num_threads(),
+        n => n,
+    };
+
+    if n_threads <= 1 || l <= 1 {
+        return predict_rows(&model, &kwargs, ca, 0);
     }
 
-    StructChunked::from_series(inputs[0].name().clone(), ca.len(), res.iter())
-        .map(|x| x.into_series())
+    // Split the rows into one contiguous range per thread, predict them on the shared rayon
+    // pool (so that concurrent batches don't oversubscribe the machine), and stitch the
+    // results back together in order.
+    let range_len = l.div_ceil(n_threads);
+    let offsets: Vec<usize> = (0..l).step_by(range_len).collect();
+    let parts: Vec<PolarsResult<Series>> = offsets
+        .into_par_iter()
+        .map(|offset| {
+            let part = ca.slice(offset as i64, range_len);
+            predict_rows(&model, &kwargs, &part, offset)
+        })
+        .collect();
+
+    let mut out: Option<Series> = None;
+    for part in parts {
+        let part = part?;
+        match &mut out {
+            Some(out) => {
+                out.append(&part)?;
+            },
+            None => out = Some(part),
+        }
+    }
+    Ok(out.unwrap())
 }
@@ -0,0 +1,167 @@
+//! Matching of large lists of literal terms with Aho-Corasick automata,
+//! used by `scrub` and the `contains_any` and `count_matches` expressions.
+use std::hash::Hash;
+use std::sync::Arc;
+
+use aho_corasick::{AhoCorasick, AhoCorasickBuilder, MatchKind};
+use polars::prelude::*;
+use pyo3_polars::derive::polars_expr;
+use serde::Deserialize;
+use xxhash_rust::xxh3::Xxh3;
+
+use crate::registry;
+
+#[derive(Deserialize, Hash)]
+pub(crate) struct LiteralsKwargs {
+    terms: Vec<String>,
+    /// ASCII case insensitive matching.
+    case_insensitive: bool,
+    /// Only match terms that are not part of a longer word.
+    whole_word: bool,
+}
+
+pub(crate) struct LiteralMatcher {
+    automaton: AhoCorasick,
+    whole_word: bool,
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether `c` belongs to a script written without spaces between words (Thai, Lao, Tibetan,
+/// Myanmar, Khmer, Chinese and Japanese), where any character boundary can be a word boundary.
+fn is_unsegmented(c: char) -> bool {
+    matches!(c,
+        '\u{0E00}'..='\u{0FFF}'
+        | '\u{1000}'..='\u{109F}'
+        | '\u{1780}'..='\u{17FF}'
+        | '\u{3040}'..='\u{30FF}'
+        | '\u{3400}'..='\u{4DBF}'
+        | '\u{4E00}'..='\u{9FFF}'
+        | '\u{F900}'..='\u{FAFF}'
+        | '\u{FF66}'..='\u{FF9F}'
+        | '\u{20000}'..='\u{2FFFF}')
+}
+
+/// Whether there is a word boundary between the characters `a` and `b`.
+fn is_boundary(a: Option<char>, b: Option<char>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => {
+            !is_word_char(a) || !is_word_char(b) || is_unsegmented(a) || is_unsegmented(b)
+        },
+        _ => true,
+    }
+}
+
+/// Keeps the leftmost longest of overlapping (non-empty) `matches`, given as byte ranges.
+pub(crate) fn leftmost_longest(matches: &mut Vec<(usize, usize)>) {
+    matches.sort_unstable_by_key(|&(start, end)| (start, std::cmp::Reverse(end)));
+    let mut last_end = 0;
+    matches.retain(|&(start, end)| {
+        let keep = start >= last_end && end > start;
+        if keep {
+            last_end = end;
+        }
+        keep
+    });
+}
+
+impl LiteralMatcher {
+    pub(crate) fn new(kwargs: &LiteralsKwargs) -> PolarsResult<Self> {
+        Self::from_terms(&kwargs.terms, kwargs.case_insensitive, kwargs.whole_word)
+    }
+
+    /// Builds the matcher of `kwargs`, caching it in the pattern registry,
+    /// so that expressions don't rebuild their automaton for every batch.
+    fn load(kwargs: &LiteralsKwargs) -> PolarsResult<Arc<Self>> {
+        let mut hasher = Xxh3::new();
+        kwargs.hash(&mut hasher);
+        let key = format!("{:032x}", hasher.digest128());
+        registry::load_pattern("literals", key, || {
+            Self::new(kwargs).map_err(|e| e.to_string())
+        })
+        .map_err(|e| polars_err!(ComputeError: "{}", e))
+    }
+
+    pub(crate) fn from_terms<T: AsRef<str>>(
+        terms: &[T],
+        case_insensitive: bool,
+        whole_word: bool,
+    ) -> PolarsResult<Self> {
+        // Whole word matching filters all (overlapping) matches, which requires standard
+        // match semantics, since the longest match at a position need not be a whole word.
+        let match_kind = if whole_word {
+            MatchKind::Standard
+        } else {
+            MatchKind::LeftmostLongest
+        };
+        let automaton = AhoCorasickBuilder::new()
+            .ascii_case_insensitive(case_insensitive)
+            .match_kind(match_kind)
+            .build(
+                terms
+                    .iter()
+                    .map(AsRef::as_ref)
+                    .filter(|term| !term.is_empty()),
+            )
+            .map_err(|e| polars_err!(ComputeError: "failed to build literal matcher: {}", e))?;
+        Ok(Self {
+            automaton,
+            whole_word,
+        })
+    }
+
+    fn is_whole_word(txt: &str, start: usize, end: usize) -> bool {
+        let inner = &txt[start..end];
+        is_boundary(txt[..start].chars().next_back(), inner.chars().next())
+            && is_boundary(inner.chars().next_back(), txt[end..].chars().next())
+    }
+
+    /// The non-overlapping (leftmost longest) matches in `txt`, as byte ranges.
+    pub(crate) fn find_iter(&self, txt: &str) -> Vec<(usize, usize)> {
+        if !self.whole_word {
+            return self
+                .automaton
+                .find_iter(txt)
+                .map(|m| (m.start(), m.end()))
+                .collect();
+        }
+        let mut matches: Vec<(usize, usize)> = self
+            .automaton
+            .find_overlapping_iter(txt)
+            .map(|m| (m.start(), m.end()))
+            .filter(|&(start, end)| Self::is_whole_word(txt, start, end))
+            .collect();
+        leftmost_longest(&mut matches);
+        matches
+    }
+
+    fn is_match(&self, txt: &str) -> bool {
+        if self.whole_word {
+            self.automaton
+                .find_overlapping_iter(txt)
+                .any(|m| Self::is_whole_word(txt, m.start(), m.end()))
+        } else {
+            self.automaton.is_match(txt)
+        }
+    }
+}
+
+#[polars_expr(output_type = Boolean)]
+fn contains_any(inputs: &[Series], kwargs: LiteralsKwargs) -> PolarsResult<Series> {
+    let ca: &StringChunked = inputs[0].str()?;
+    let matcher = LiteralMatcher::load(&kwargs)?;
+    let out: BooleanChunked =
+        ca.apply_nonnull_values_generic(DataType::Boolean, |txt| matcher.is_match(txt));
+    Ok(out.into_series())
+}
+
+#[polars_expr(output_type = UInt32)]
+fn count_matches(inputs: &[Series], kwargs: LiteralsKwargs) -> PolarsResult<Series> {
+    let ca: &StringChunked = inputs[0].str()?;
+    let matcher = LiteralMatcher::load(&kwargs)?;
+    let out: UInt32Chunked = ca
+        .apply_nonnull_values_generic(DataType::UInt32, |txt| matcher.find_iter(txt).len() as u32);
+    Ok(out.into_series())
+}
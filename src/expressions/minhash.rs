@@ -7,10 +7,11 @@ use pyo3_polars::derive::polars_expr;
 use rand::distr::uniform::Uniform;
 use rand::prelude::{Rng, SeedableRng, StdRng};
 use rand::RngExt;
-use regex::Regex;
 use serde::Deserialize;
 use xxhash_rust::xxh3::{xxh3_128, Xxh3Builder};
 
+use super::words::{Builtin, WordTokenizer};
+
 const MP: u64 = (1 << 61) - 1;
 const MP_128: u128 = MP as u128;
 
@@ -84,8 +85,8 @@ impl MinHash {
         Self::from_rng(&mut StdRng::from_seed(seed), buckets, bsize, window)
     }
 
-    fn mk_minhash<'a>(&self, vals: impl Iterator<Item = &'a str>) -> Vec<u64> {
-        let mut builder: VecDeque<&str> = VecDeque::with_capacity(self.window + 1);
+    fn mk_minhash<'a>(&self, vals: impl Iterator<Item = &'a [u8]>) -> Vec<u64> {
+        let mut builder: VecDeque<&[u8]> = VecDeque::with_capacity(self.window + 1);
         let minhash: &mut [u64] = &mut vec![u64::MAX; self.hashes()][..];
         //let mut minhash: Vec<u64> = vec![u64::MAX; self.hashes()];
         vals.filter_map(|w| {
@@ -94,7 +95,7 @@ impl MinHash {
             if builder.len() == self.window {
                 let mut hasher = self.hash_builder.build_hasher();
                 for v in &builder {
-                    hasher.update(v.as_bytes());
+                    hasher.update(v);
                     hasher.write_u8(0xff);
                 }
                 Some(mod61(hasher.digest()))
@@ -109,7 +110,7 @@ impl MinHash {
         minhash.to_vec()
     }
 
-    fn mk_buckets<'a>(&self, vals: impl Iterator<Item = &'a str>) -> Vec<u128> {
+    fn mk_buckets<'a>(&self, vals: impl Iterator<Item = &'a [u8]>) -> Vec<u128> {
         // Take a `bucket * bsize` vector of minhashes, buckets them into
         // `buckets` chunks of size `bsize`, and hash each bucket into a u128 hash.
         // (Should be fine, unless we expect 2^64 different values, which we don't,
@@ -120,7 +121,7 @@ impl MinHash {
             .collect()
     }
 
-    fn apply_str<'a>(&self, vals: impl Iterator<Item = &'a str>) -> String {
+    fn apply_str<'a>(&self, vals: impl Iterator<Item = &'a [u8]>) -> String {
         // Construct a hex string representation of the bucket hashes.
         if self.bsize > 1 {
             hex::encode(into_bytes!(self.mk_buckets(vals)))
@@ -133,6 +134,7 @@ impl MinHash {
 #[derive(Deserialize)]
 struct MinHashKwargs {
     tokenizer_pattern: String,
+    word_tokenizer: Option<Builtin>,
     seed: [u8; 32],
     buckets: usize,
     bsize: usize,
@@ -141,12 +143,12 @@ struct MinHashKwargs {
 
 #[polars_expr(output_type = String)]
 fn minhash(inputs: &[Series], kwargs: MinHashKwargs) -> PolarsResult<Series> {
-    let tokenizer: Regex = Regex::new(&kwargs.tokenizer_pattern)?;
+    let tokenizer = WordTokenizer::new(&kwargs.tokenizer_pattern, kwargs.word_tokenizer)?;
     let ca: &StringChunked = inputs[0].str()?;
 
     let hasher = MinHash::from_seed(kwargs.seed, kwargs.buckets, kwargs.bsize, kwargs.window);
     let out = ca.apply_into_string_amortized(|txt: &str, res: &mut String| {
-        res.push_str(&hasher.apply_str(tokenizer.find_iter(txt).map(|x| x.as_str())));
+        res.push_str(&hasher.apply_str(tokenizer.tokens(txt)));
     });
 
     Ok(out.into_series())
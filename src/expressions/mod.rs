@@ -1,9 +1,18 @@
 #![allow(clippy::unused_unit)]
 
+mod blocklist;
 mod compression;
-mod fasttext;
+pub(crate) mod fasttext;
+mod literals;
 mod minhash;
 mod misc;
+mod packing;
+mod pii;
+mod pseudonym;
 mod repetition;
-mod scrub;
-mod tokenize;
+pub(crate) mod scrub;
+mod secrets;
+pub(crate) mod tokenize;
+mod url;
+mod utils;
+pub(crate) mod words;
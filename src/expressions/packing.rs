@@ -0,0 +1,232 @@
+use std::collections::BTreeMap;
+
+use polars::prelude::*;
+use polars_arrow::array::{Array, PrimitiveArray};
+use pyo3_polars::derive::polars_expr;
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+enum Strategy {
+    Greedy,
+    BestFit,
+}
+
+#[derive(Deserialize)]
+struct PackKwargs {
+    seq_len: usize,
+    eos_id: u32,
+    pad_id: u32,
+    strategy: Strategy,
+    drop_last: bool,
+}
+
+/// Fixed length sequences of token ids, stored back to back. Along with each token we store
+/// its segment id: the (1-based) index of the document it belongs to within its sequence,
+/// or 0 for padding.
+struct Sequences {
+    seq_len: usize,
+    ids: Vec<u32>,
+    segment_ids: Vec<u32>,
+}
+
+impl Sequences {
+    fn new(seq_len: usize) -> Self {
+        Self {
+            seq_len,
+            ids: Vec::new(),
+            segment_ids: Vec::new(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.ids.len() / self.seq_len
+    }
+
+    fn push(&mut self, id: u32, segment_id: u32) {
+        self.ids.push(id);
+        self.segment_ids.push(segment_id);
+    }
+
+    /// Pads the last sequence to `seq_len` tokens.
+    fn pad(&mut self, pad_id: u32) {
+        while !self.ids.len().is_multiple_of(self.seq_len) {
+            self.push(pad_id, 0);
+        }
+    }
+
+    /// Drops the last sequence if it is shorter than `seq_len` tokens.
+    fn truncate(&mut self) {
+        let len = self.len() * self.seq_len;
+        self.ids.truncate(len);
+        self.segment_ids.truncate(len);
+    }
+}
+
+/// A part of a document followed by its EOS token, i.e. the positions `start..end`
+/// of `doc ++ [eos]`.
+#[derive(Clone, Copy)]
+struct Piece<'a> {
+    doc: &'a [u32],
+    start: usize,
+    end: usize,
+}
+
+impl Piece<'_> {
+    fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    fn tokens(&self, eos_id: u32) -> impl Iterator<Item = u32> + '_ {
+        (self.start..self.end).map(move |i| self.doc.get(i).copied().unwrap_or(eos_id))
+    }
+}
+
+/// Concatenates all documents (separated by EOS) and cuts the result into sequences.
+fn pack_greedy(docs: &[&[u32]], kwargs: &PackKwargs, out: &mut Sequences) {
+    let mut segment_id = 0;
+    for doc in docs {
+        let mut new_doc = true;
+        for &id in doc.iter().chain(std::iter::once(&kwargs.eos_id)) {
+            if out.ids.len().is_multiple_of(out.seq_len) {
+                segment_id = 0;
+                new_doc = true;
+            }
+            if new_doc {
+                segment_id += 1;
+                new_doc = false;
+            }
+            out.push(id, segment_id);
+        }
+    }
+    if kwargs.drop_last {
+        out.truncate();
+    } else {
+        out.pad(kwargs.pad_id);
+    }
+}
+
+/// Splits documents (followed by EOS) into pieces of at most `seq_len` tokens,
+/// and packs the pieces into sequences using best-fit decreasing bin packing,
+/// so that only documents longer than `seq_len` are split across sequences.
+fn pack_best_fit(docs: &[&[u32]], kwargs: &PackKwargs, out: &mut Sequences) {
+    let seq_len = out.seq_len;
+    let mut pieces: Vec<Piece> = Vec::new();
+    for doc in docs {
+        let len = doc.len() + 1;
+        for start in (0..len).step_by(seq_len) {
+            pieces.push(Piece {
+                doc,
+                start,
+                end: len.min(start + seq_len),
+            });
+        }
+    }
+    pieces.sort_by_key(|piece| std::cmp::Reverse(piece.len()));
+
+    // bins[i] holds the pieces of sequence i, and free maps remaining space to the bins with
+    // that much space left.
+    let mut bins: Vec<Vec<Piece>> = Vec::new();
+    let mut free: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+    for piece in pieces {
+        let len = piece.len();
+        let fit = free
+            .range_mut(len..)
+            .next()
+            .map(|(&space, bins)| (space, bins.pop()));
+        let (bin, space) = match fit {
+            Some((space, Some(bin))) => (bin, space),
+            _ => {
+                bins.push(Vec::new());
+                (bins.len() - 1, seq_len)
+            },
+        };
+        if free.get(&space).is_some_and(|bins| bins.is_empty()) {
+            free.remove(&space);
+        }
+        bins[bin].push(piece);
+        if space > len {
+            free.entry(space - len).or_default().push(bin);
+        }
+    }
+
+    for bin in bins {
+        for (segment_id, piece) in bin.iter().enumerate() {
+            for id in piece.tokens(kwargs.eos_id) {
+                out.push(id, segment_id as u32 + 1);
+            }
+        }
+        out.pad(kwargs.pad_id);
+    }
+}
+
+fn pack_output(input_fields: &[Field], kwargs: PackKwargs) -> PolarsResult<Field> {
+    let field = &input_fields[0];
+    match field.dtype() {
+        DataType::List(inner) if inner.is_integer() => {
+            let fields = vec![
+                Field::new(
+                    "ids".into(),
+                    DataType::Array(Box::new(DataType::UInt32), kwargs.seq_len),
+                ),
+                Field::new(
+                    "segment_ids".into(),
+                    DataType::Array(Box::new(DataType::UInt32), kwargs.seq_len),
+                ),
+            ];
+            Ok(Field::new(field.name.clone(), DataType::Struct(fields)))
+        },
+        dtype => polars_bail!(InvalidOperation: "expected list of token ids, got {}", dtype),
+    }
+}
+
+#[polars_expr(output_type_func_with_kwargs = pack_output)]
+fn pack_sequences(inputs: &[Series], kwargs: PackKwargs) -> PolarsResult<Series> {
+    if kwargs.seq_len == 0 {
+        polars_bail!(InvalidOperation: "seq_len must be positive")
+    }
+    let s = inputs[0].cast(&DataType::List(Box::new(DataType::UInt32)))?;
+    let ca = s.list()?.rechunk();
+
+    let mut docs: Vec<&[u32]> = Vec::with_capacity(ca.len());
+    for arr in ca.downcast_iter() {
+        let values = arr
+            .values()
+            .as_any()
+            .downcast_ref::<PrimitiveArray<u32>>()
+            .unwrap();
+        if values.null_count() > 0 {
+            polars_bail!(InvalidOperation: "token ids must not contain nulls")
+        }
+        let values = values.values().as_slice();
+        for i in 0..arr.len() {
+            if arr.is_valid(i) {
+                let (start, end) = arr.offsets().start_end(i);
+                docs.push(&values[start..end]);
+            }
+        }
+    }
+
+    let mut out = Sequences::new(kwargs.seq_len);
+    match kwargs.strategy {
+        Strategy::Greedy => pack_greedy(&docs, &kwargs, &mut out),
+        Strategy::BestFit => pack_best_fit(&docs, &kwargs, &mut out),
+    }
+
+    let len = out.len();
+    let array = |name: &str, values: Vec<u32>| {
+        ArrayChunked::from_aligned_values(
+            name.into(),
+            &DataType::UInt32,
+            kwargs.seq_len,
+            UInt32Chunked::from_vec(name.into(), values)
+                .chunks()
+                .clone(),
+            len,
+        )
+        .into_series()
+    };
+    let fields = [array("ids", out.ids), array("segment_ids", out.segment_ids)];
+    StructChunked::from_series(inputs[0].name().clone(), len, fields.iter())
+        .map(|x| x.into_series())
+}
@@ -0,0 +1,218 @@
+//! Built-in PII detectors, used by `scrub` and `find_spans` alongside user regexes.
+//!
+//! Each detector finds candidates with a (permissive) regex, and then validates them,
+//! e.g. by checking the Luhn checksum of credit card numbers, or parsing IPv6 addresses.
+use std::net::Ipv6Addr;
+use std::str::FromStr;
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::Deserialize;
+
+#[derive(Deserialize, Clone, Copy, Hash)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Detector {
+    Email,
+    Ipv4,
+    Ipv6,
+    Phone,
+    CreditCard,
+    Iban,
+    Ssn,
+    ApiKey,
+}
+
+/// Prefixes of well known API keys and tokens (OpenAI, GitHub, Slack, AWS, Google, Stripe,
+/// GitLab, Hugging Face, npm).
+const API_KEY_PREFIXES: &str = r"sk-(?:proj-)?|gh[pousr]_|github_pat_|xox[abposr]-|AKIA|ASIA|AIza|[rsp]k_(?:live|test)_|glpat-|hf_|npm_";
+
+static API_KEY_PREFIX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(&format!("^(?:{})", API_KEY_PREFIXES)).unwrap());
+
+impl Detector {
+    fn pattern(&self) -> String {
+        match self {
+            Self::Email => {
+                r"(?i)\b[a-z0-9][a-z0-9._%+-]*@(?:[a-z0-9](?:[a-z0-9-]*[a-z0-9])?\.)+[a-z]{2,}\b"
+                    .into()
+            },
+            Self::Ipv4 => r"\b(?:\d{1,3}\.){3}\d{1,3}\b".into(),
+            // Runs of hex digits, colons and dots (for embedded IPv4 addresses) containing a
+            // colon, which are validated by parsing.
+            Self::Ipv6 => r"(?i)[0-9a-f]*:[0-9a-f:.]*[0-9a-f:]".into(),
+            Self::Phone => {
+                r"(?:\+\d{1,3}[ .-]?)?(?:\(\d{1,4}\)[ .-]?)?\d{2,4}(?:[ .-]?\d{2,4}){1,4}".into()
+            },
+            Self::CreditCard => r"\b\d(?:[ -]?\d){12,18}\b".into(),
+            Self::Iban => r"\b[A-Z]{2}\d{2}(?: ?[A-Z0-9]){11,30}\b".into(),
+            Self::Ssn => r"\b\d{3}-\d{2}-\d{4}\b".into(),
+            Self::ApiKey => format!(r"\b(?:{})[A-Za-z0-9_-]{{16,}}", API_KEY_PREFIXES),
+        }
+    }
+
+    /// Validates the candidate `txt[start..end]`, returning the (possibly shortened) match.
+    fn validate(&self, txt: &str, start: usize, end: usize) -> Option<(usize, usize)> {
+        let candidate = &txt[start..end];
+        let valid = match self {
+            Self::Email => {
+                let (local, _) = candidate.split_once('@')?;
+                local.len() <= 64 && !candidate.contains("..")
+            },
+            Self::Ipv4 => {
+                // Reject parts of longer dotted sequences, e.g. version numbers.
+                let before = txt[..start].chars().next_back();
+                let after = txt[end..].chars().next();
+                let dotted = |c: Option<char>| c == Some('.');
+                !dotted(before)
+                    && !(dotted(after) && txt[end + 1..].starts_with(|c: char| c.is_ascii_digit()))
+                    && candidate
+                        .split('.')
+                        .all(|octet| octet.parse::<u8>().is_ok())
+            },
+            Self::Ipv6 => {
+                let isolated = !txt[..start]
+                    .chars()
+                    .next_back()
+                    .is_some_and(char::is_alphanumeric)
+                    && !txt[end..].chars().next().is_some_and(char::is_alphanumeric);
+                isolated
+                    && candidate.matches(':').count() >= 2
+                    && candidate.contains(|c: char| c.is_ascii_hexdigit())
+                    && Ipv6Addr::from_str(candidate).is_ok()
+            },
+            Self::Phone => {
+                let digits = candidate.bytes().filter(u8::is_ascii_digit).count();
+                // Plain numbers are too ambiguous, so require a country code or separators.
+                let formatted =
+                    candidate.starts_with('+') || candidate.contains(|c: char| !c.is_ascii_digit());
+                let isolated = !txt[..start]
+                    .chars()
+                    .next_back()
+                    .is_some_and(|c| c.is_alphanumeric() || c == '+')
+                    && !txt[end..].chars().next().is_some_and(char::is_alphanumeric);
+                // Numbers end in a group of at least 3 digits, e.g. `123-4567` or `12 345 678`.
+                let last_group = candidate.rsplit(|c: char| !c.is_ascii_digit()).next();
+                isolated
+                    && formatted
+                    && (10..=15).contains(&digits)
+                    && last_group.is_some_and(|group| group.len() >= 3)
+            },
+            Self::CreditCard => luhn(candidate),
+            Self::Iban => {
+                // The pattern may extend into a following word, so try shorter candidates,
+                // ending at the spaces of the candidate.
+                let ends = candidate
+                    .match_indices(' ')
+                    .map(|(i, _)| start + i)
+                    .rev()
+                    .filter(|&end| txt[start..end].len() >= 15);
+                return std::iter::once(end)
+                    .chain(ends)
+                    .find(|&end| iban(&txt[start..end]))
+                    .map(|end| (start, end));
+            },
+            Self::Ssn => {
+                let area = &candidate[0..3];
+                area != "000"
+                    && area != "666"
+                    && !area.starts_with('9')
+                    && &candidate[4..6] != "00"
+                    && &candidate[7..11] != "0000"
+            },
+            Self::ApiKey => {
+                let prefix = API_KEY_PREFIX.find(candidate).map_or(0, |m| m.end());
+                shannon_entropy(&candidate[prefix..]) >= 3.0
+            },
+        };
+        valid.then_some((start, end))
+    }
+}
+
+/// Whether the digits of `number` (ignoring other characters) pass the Luhn checksum.
+fn luhn(number: &str) -> bool {
+    let digits: Vec<u32> = number.chars().filter_map(|c| c.to_digit(10)).collect();
+    if !(13..=19).contains(&digits.len()) {
+        return false;
+    }
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(i, &d)| {
+            if i % 2 == 1 {
+                let d = d * 2;
+                if d > 9 {
+                    d - 9
+                } else {
+                    d
+                }
+            } else {
+                d
+            }
+        })
+        .sum();
+    sum.is_multiple_of(10)
+}
+
+/// Whether `iban` (ignoring spaces) is a valid IBAN according to its mod-97 checksum.
+fn iban(iban: &str) -> bool {
+    let chars: Vec<char> = iban.chars().filter(|c| *c != ' ').collect();
+    if !(15..=34).contains(&chars.len()) {
+        return false;
+    }
+    // Move the country code and check digits to the end, and convert letters to 10..=35.
+    let mut remainder: u32 = 0;
+    for c in chars[4..].iter().chain(&chars[..4]) {
+        let Some(value) = c.to_digit(36) else {
+            return false;
+        };
+        remainder = if value < 10 {
+            (remainder * 10 + value) % 97
+        } else {
+            (remainder * 100 + value) % 97
+        };
+    }
+    remainder == 1
+}
+
+/// The Shannon entropy of the characters of `s`, in bits per character.
+pub(crate) fn shannon_entropy(s: &str) -> f64 {
+    let mut counts: std::collections::HashMap<char, usize> = Default::default();
+    let mut total = 0;
+    for c in s.chars() {
+        *counts.entry(c).or_default() += 1;
+        total += 1;
+    }
+    counts
+        .values()
+        .map(|&count| {
+            let p = count as f64 / total as f64;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+/// A compiled detector.
+pub(crate) struct PiiMatcher {
+    detector: Detector,
+    regex: Regex,
+}
+
+impl PiiMatcher {
+    pub(crate) fn new(detector: Detector) -> Self {
+        Self {
+            detector,
+            regex: Regex::new(&detector.pattern()).unwrap(),
+        }
+    }
+
+    /// The byte ranges of all validated matches in `txt`.
+    pub(crate) fn find_iter<'a>(
+        &'a self,
+        txt: &'a str,
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        self.regex
+            .find_iter(txt)
+            .filter_map(move |m| self.detector.validate(txt, m.start(), m.end()))
+    }
+}
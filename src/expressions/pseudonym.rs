@@ -0,0 +1,77 @@
+//! Deterministic surrogates for scrubbed matches, derived from a keyed hash (HMAC-SHA256),
+//! so that the same entity maps to the same surrogate across a corpus.
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+/// The shape of the surrogate of a match.
+#[derive(Clone, Copy)]
+pub(crate) enum Format {
+    /// `user_<hex>@example.com`.
+    Email,
+    /// A random IPv4 address.
+    Ipv4,
+    /// A random IPv6 address.
+    Ipv6,
+    /// Digits and letters are replaced by random ASCII characters of the same class (upper case
+    /// letters by upper case ones, other letters, including non-ASCII ones, by lower case ones),
+    /// and all other characters are kept, preserving e.g. the formatting of phone numbers.
+    Preserve,
+}
+
+pub(crate) struct Pseudonymizer {
+    mac: Hmac<Sha256>,
+}
+
+impl Pseudonymizer {
+    pub(crate) fn new(secret: &str) -> Self {
+        Self {
+            mac: Hmac::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size"),
+        }
+    }
+
+    /// Pseudo-random bytes determined by the secret and `text`, as the concatenation of
+    /// `HMAC(secret, block || text)` for block = 0, 1, ...
+    fn stream<'a>(&'a self, text: &'a str) -> impl Iterator<Item = u8> + 'a {
+        (0u32..).flat_map(move |block| {
+            let mut mac = self.mac.clone();
+            mac.update(&block.to_be_bytes());
+            mac.update(text.as_bytes());
+            mac.finalize().into_bytes()
+        })
+    }
+
+    pub(crate) fn surrogate(&self, format: Format, text: &str, out: &mut String) {
+        let mut stream = self.stream(text);
+        let mut next = || stream.next().unwrap();
+        match format {
+            Format::Email => {
+                out.push_str("user_");
+                // 64 bits, so that distinct emails practically never share a surrogate.
+                for _ in 0..8 {
+                    out.push_str(&format!("{:02x}", next()));
+                }
+                out.push_str("@example.com");
+            },
+            Format::Ipv4 => {
+                let ip = Ipv4Addr::new(next(), next(), next(), next());
+                out.push_str(&ip.to_string());
+            },
+            Format::Ipv6 => {
+                let ip = Ipv6Addr::from(std::array::from_fn::<u8, 16, _>(|_| next()));
+                out.push_str(&ip.to_string());
+            },
+            Format::Preserve => {
+                for c in text.chars() {
+                    out.push(match c {
+                        c if c.is_numeric() => (b'0' + next() % 10) as char,
+                        c if c.is_uppercase() => (b'A' + next() % 26) as char,
+                        c if c.is_alphabetic() => (b'a' + next() % 26) as char,
+                        c => c,
+                    });
+                }
+            },
+        }
+    }
+}
@@ -5,10 +5,11 @@ use itertools::izip;
 use polars::prelude::*;
 use polars_arrow::bitmap::{Bitmap, MutableBitmap};
 use pyo3_polars::derive::polars_expr;
-use regex::Regex;
 use serde::Deserialize;
 use xxhash_rust::xxh3::Xxh3Builder;
 
+use super::words::{char_len, Builtin, WordTokenizer};
+
 fn ratio(num: usize, den: usize) -> f32 {
     ((num as f64) / (den as f64)) as f32
 }
@@ -17,14 +18,14 @@ fn dup_ngrams_hash<'a>(
     hash_builder: &Xxh3Builder,
     num_top: usize,
     num_dup: usize,
-    vals: impl Iterator<Item = &'a str>,
+    vals: impl Iterator<Item = &'a [u8]>,
 ) -> Vec<f32> {
     // Counts duplicate and top ngrams, avoiding overlap for duplicate ngrams.
     let mut seen: HashSet<u128> = HashSet::new();
     let mut counts: HashMap<u128, usize> = HashMap::new();
     //sbuf tracks the last N seen tokens
     //lbuf tracks the cumulative length of the last N seen tokens.
-    let mut sbuf: VecDeque<&str> = VecDeque::with_capacity(num_dup + 1);
+    let mut sbuf: VecDeque<&[u8]> = VecDeque::with_capacity(num_dup + 1);
     let mut lbuf: VecDeque<usize> = VecDeque::with_capacity(num_dup + 1);
     // last[n] is the leftmost position of the last duplicate "n"-gram.
     // It is used to avoid double counting overlapping duplicates.
@@ -35,7 +36,7 @@ fn dup_ngrams_hash<'a>(
     let mut tot: usize = 0;
 
     for (pos, v) in vals.enumerate() {
-        let vlen = v.chars().count();
+        let vlen = char_len(v);
         lbuf.push_front(0);
         sbuf.push_front(v);
         lbuf.truncate(num_dup);
@@ -50,7 +51,7 @@ fn dup_ngrams_hash<'a>(
         // sbuf = [mat, the, on, sat]
         for (n, gram, dup) in izip!(0..sbuf.len(), &sbuf, &mut *dups) {
             lbuf[n] += vlen;
-            hasher.update(gram.as_bytes());
+            hasher.update(gram);
             hasher.write_u8(0xff);
             let ngram = hasher.digest128();
             if n < num_top {
@@ -115,13 +116,14 @@ fn repetition_output(input_fields: &[Field], kwargs: RepetitionKwargs) -> Polars
 #[derive(Deserialize)]
 struct RepetitionKwargs {
     tokenizer_pattern: String,
+    word_tokenizer: Option<Builtin>,
     num_top: usize,
     num_dup: usize,
 }
 
 #[polars_expr(output_type_func_with_kwargs = repetition_output)]
 fn repetition_signals(inputs: &[Series], kwargs: RepetitionKwargs) -> PolarsResult<Series> {
-    let tokenizer: Regex = Regex::new(&kwargs.tokenizer_pattern)?;
+    let tokenizer = WordTokenizer::new(&kwargs.tokenizer_pattern, kwargs.word_tokenizer)?;
     let hash_builder = Xxh3Builder::new().with_seed(0x5eed);
     let ca: &StringChunked = inputs[0].str()?;
 
@@ -135,7 +137,7 @@ fn repetition_signals(inputs: &[Series], kwargs: RepetitionKwargs) -> PolarsResu
                 &hash_builder,
                 kwargs.num_top,
                 kwargs.num_dup,
-                tokenizer.find_iter(txt).map(|x| x.as_str()),
+                tokenizer.tokens(txt),
             )
         }) {
             Some(signals) => {
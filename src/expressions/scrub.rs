@@ -1,65 +1,525 @@
+use std::collections::BTreeMap;
+use std::hash::Hash;
+use std::sync::Arc;
+
 use polars::prelude::*;
+use polars_arrow::bitmap::MutableBitmap;
 use pyo3_polars::derive::polars_expr;
 use regex::{Regex, RegexSet};
 use serde::Deserialize;
+use xxhash_rust::xxh3::Xxh3;
 
-fn fuse_bounds(
-    bounds: impl Iterator<Item = (usize, usize)>,
-) -> impl Iterator<Item = (usize, usize)> {
-    let mut bounds: Vec<(usize, usize)> = bounds.collect();
-    if bounds.is_empty() {
-        Vec::new().into_iter()
-    } else {
-        bounds.sort_unstable_by_key(|k| k.0);
-
-        let mut merged = Vec::with_capacity(bounds.len());
-        let mut current_merge = bounds[0];
-
-        for &(next_start, next_stop) in &bounds[1..] {
-            if next_start <= current_merge.1 {
-                current_merge.1 = current_merge.1.max(next_stop);
-            } else {
-                merged.push(current_merge);
-                current_merge = (next_start, next_stop);
+use super::literals::{LiteralMatcher, LiteralsKwargs};
+use super::pii::{Detector, PiiMatcher};
+use super::pseudonym::{Format, Pseudonymizer};
+use super::secrets::{SecretMatcher, SecretRule};
+use super::utils::list_from_offsets;
+use crate::registry;
+
+/// A match of the rule with index `rule`.
+#[derive(Clone, Copy)]
+struct Span {
+    start: usize,
+    end: usize,
+    rule: usize,
+}
+
+/// Merges overlapping (or touching) spans, attributing each merged span to its first span.
+fn fuse_spans(mut spans: Vec<Span>) -> Vec<Span> {
+    spans.sort_unstable_by_key(|span| (span.start, span.rule));
+    let mut merged: Vec<Span> = Vec::with_capacity(spans.len());
+    for span in spans {
+        match merged.last_mut() {
+            Some(current) if span.start <= current.end => {
+                current.end = current.end.max(span.end);
+            },
+            _ => merged.push(span),
+        }
+    }
+    merged
+}
+
+#[derive(Deserialize, Hash)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum MatcherKwargs {
+    Regex { pattern: String },
+    Pii { detector: Detector },
+    Literals(LiteralsKwargs),
+    Secret { rule: SecretRule },
+}
+
+impl MatcherKwargs {
+    fn format(&self) -> Format {
+        match self {
+            Self::Pii {
+                detector: Detector::Email,
+            } => Format::Email,
+            Self::Pii {
+                detector: Detector::Ipv4,
+            } => Format::Ipv4,
+            Self::Pii {
+                detector: Detector::Ipv6,
+            } => Format::Ipv6,
+            _ => Format::Preserve,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RuleKwargs {
+    name: String,
+    #[serde(flatten)]
+    matcher: MatcherKwargs,
+    replacement: String,
+    priority: u32,
+    /// Whether the replacement may refer to capture groups (only for regex rules).
+    #[serde(default)]
+    template: bool,
+}
+
+/// A named pattern or detector. When matches of different rules overlap, the rule with the
+/// lowest `priority` wins, and overlapping matches of rules with the same priority are merged.
+struct Rule {
+    name: String,
+    replacement: String,
+    priority: u32,
+    /// The format of pseudonymized matches.
+    format: Format,
+    /// Whether the replacement is a template referring to capture groups of the rule's regex.
+    template: bool,
+}
+
+/// The compiled matchers of a list of rules. These are cached in the pattern registry (which
+/// keeps the 256 most recently used patterns), keyed by a hash of the matchers, since
+/// compiling hundreds of patterns can dominate small batches.
+struct Matchers {
+    /// The regexes of the regex rules, with `regex_rules` mapping their index to the rule index.
+    pattern_set: RegexSet,
+    patterns: Vec<Regex>,
+    regex_rules: Vec<usize>,
+    detectors: Vec<(usize, PiiMatcher)>,
+    literals: Vec<(usize, LiteralMatcher)>,
+    secrets: Vec<(usize, SecretMatcher)>,
+}
+
+impl Matchers {
+    fn new(matchers: &[&MatcherKwargs]) -> PolarsResult<Self> {
+        let mut regexes: Vec<&str> = Vec::new();
+        let mut regex_rules: Vec<usize> = Vec::new();
+        let mut detectors: Vec<(usize, PiiMatcher)> = Vec::new();
+        let mut literals: Vec<(usize, LiteralMatcher)> = Vec::new();
+        let mut secrets: Vec<(usize, SecretMatcher)> = Vec::new();
+        for (index, matcher) in matchers.iter().enumerate() {
+            match matcher {
+                MatcherKwargs::Regex { pattern } => {
+                    regexes.push(pattern);
+                    regex_rules.push(index);
+                },
+                MatcherKwargs::Pii { detector } => {
+                    detectors.push((index, PiiMatcher::new(*detector)));
+                },
+                MatcherKwargs::Literals(kwargs) => {
+                    literals.push((index, LiteralMatcher::new(kwargs)?));
+                },
+                MatcherKwargs::Secret { rule } => {
+                    secrets.push((index, SecretMatcher::new(*rule)));
+                },
+            }
+        }
+        let pattern_set = RegexSet::new(regexes)?;
+        let patterns: Vec<Regex> = pattern_set
+            .patterns()
+            .iter()
+            .map(|pat| Regex::new(pat).unwrap())
+            .collect();
+        Ok(Self {
+            pattern_set,
+            patterns,
+            regex_rules,
+            detectors,
+            literals,
+            secrets,
+        })
+    }
+
+    fn load(matchers: &[&MatcherKwargs]) -> PolarsResult<Arc<Self>> {
+        let mut hasher = Xxh3::new();
+        matchers.hash(&mut hasher);
+        let key = format!("{:032x}", hasher.digest128());
+        registry::load_pattern("scrub", key, || {
+            Self::new(matchers).map_err(|e| e.to_string())
+        })
+        .map_err(|e| polars_err!(ComputeError: "{}", e))
+    }
+
+    /// The regex of the rule with index `rule`, if it is a regex rule.
+    fn regex(&self, rule: usize) -> Option<&Regex> {
+        self.regex_rules
+            .binary_search(&rule)
+            .ok()
+            .map(|index| &self.patterns[index])
+    }
+
+    /// All matches of all rules in `txt`, unordered and possibly overlapping.
+    fn matches(&self, txt: &str) -> Vec<Span> {
+        let regex_matches = self.pattern_set.matches(txt).into_iter().flat_map(|index| {
+            let rule = self.regex_rules[index];
+            self.patterns[index].find_iter(txt).map(move |m| Span {
+                start: m.start(),
+                end: m.end(),
+                rule,
+            })
+        });
+        let detector_matches = self.detectors.iter().flat_map(|(rule, detector)| {
+            detector.find_iter(txt).map(|(start, end)| Span {
+                start,
+                end,
+                rule: *rule,
+            })
+        });
+        let literal_matches = self.literals.iter().flat_map(|(rule, literals)| {
+            literals
+                .find_iter(txt)
+                .into_iter()
+                .map(|(start, end)| Span {
+                    start,
+                    end,
+                    rule: *rule,
+                })
+        });
+        let secret_matches = self.secrets.iter().flat_map(|(rule, secrets)| {
+            secrets.find_iter(txt).map(|(start, end)| Span {
+                start,
+                end,
+                rule: *rule,
+            })
+        });
+        regex_matches
+            .chain(detector_matches)
+            .chain(literal_matches)
+            .chain(secret_matches)
+            .collect()
+    }
+}
+
+struct Rules {
+    rules: Vec<Rule>,
+    matchers: Arc<Matchers>,
+}
+
+impl Rules {
+    fn new(rules: Vec<RuleKwargs>) -> PolarsResult<Self> {
+        let matchers = Matchers::load(&rules.iter().map(|rule| &rule.matcher).collect::<Vec<_>>())?;
+        let rules = rules
+            .into_iter()
+            .map(|rule| Rule {
+                format: rule.matcher.format(),
+                template: rule.template
+                    && matches!(rule.matcher, MatcherKwargs::Regex { .. })
+                    && rule.replacement.contains('$'),
+                name: rule.name,
+                replacement: rule.replacement,
+                priority: rule.priority,
+            })
+            .collect();
+        Ok(Self { rules, matchers })
+    }
+
+    fn matches(&self, txt: &str) -> Vec<Span> {
+        self.matchers.matches(txt)
+    }
+
+    /// The non-overlapping spans to replace in `txt`, ordered by position.
+    fn spans(&self, txt: &str) -> Vec<Span> {
+        let mut matches = self.matches(txt);
+        if matches.is_empty() {
+            return matches;
+        }
+        matches.sort_by_key(|span| self.rules[span.rule].priority);
+
+        // Accepted spans by start. Since these don't overlap, the spans overlapping a span
+        // are the last accepted spans starting before its end, as long as they end after its start.
+        let mut accepted: BTreeMap<usize, Span> = BTreeMap::new();
+        for level in
+            matches.chunk_by(|a, b| self.rules[a.rule].priority == self.rules[b.rule].priority)
+        {
+            for span in fuse_spans(level.to_vec()) {
+                let overlapping: Vec<usize> = accepted
+                    .range(..span.end)
+                    .rev()
+                    .take_while(|(_, other)| other.end > span.start)
+                    .map(|(&start, _)| start)
+                    .collect();
+                let Some(&first) = overlapping.last() else {
+                    accepted.insert(span.start, span);
+                    continue;
+                };
+                // The higher priority match sets the label, but the rest of the lower priority
+                // match is still redacted, as part of the same span.
+                let mut merged = Span {
+                    start: span.start.min(first),
+                    end: span.end,
+                    rule: accepted[&first].rule,
+                };
+                for start in overlapping {
+                    let other = accepted.remove(&start).unwrap();
+                    merged.end = merged.end.max(other.end);
+                }
+                accepted.insert(merged.start, merged);
             }
         }
-        merged.push(current_merge);
-        merged.into_iter()
+        accepted.into_values().collect()
     }
 }
 
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum Mode {
+    /// Replace matches with the replacement of their rule.
+    Replace,
+    /// Replace matches with deterministic surrogates of the same format.
+    Pseudonymize,
+}
+
 #[derive(Deserialize)]
 struct ScrubKwargs {
-    patterns: Vec<String>,
-    replacement: String,
+    rules: Vec<RuleKwargs>,
+    mode: Mode,
+    secret: Option<String>,
 }
 
 #[polars_expr(output_type = String)]
 fn scrub(inputs: &[Series], kwargs: ScrubKwargs) -> PolarsResult<Series> {
     let ca: &StringChunked = inputs[0].str()?;
-    let replacement = kwargs.replacement;
-    let pattern_set = RegexSet::new(kwargs.patterns)?;
-    let patterns: Vec<Regex> = pattern_set
-        .patterns()
-        .iter()
-        .map(|pat| Regex::new(pat).unwrap())
-        .collect();
+    let rules = Rules::new(kwargs.rules)?;
+    let pseudonymizer = match (kwargs.mode, &kwargs.secret) {
+        (Mode::Replace, _) => None,
+        (Mode::Pseudonymize, Some(secret)) => Some(Pseudonymizer::new(secret)),
+        (Mode::Pseudonymize, None) => {
+            polars_bail!(InvalidOperation: "pseudonymize requires a secret")
+        },
+    };
 
     let out = ca.apply_into_string_amortized(|txt: &str, res: &mut String| {
-        let bounds = pattern_set
-            .matches(txt)
-            .into_iter()
-            .map(|index| &patterns[index])
-            .flat_map(|pattern| pattern.find_iter(txt).map(move |m| (m.start(), m.end())));
-
         let mut last_stop = 0;
-        for (start, stop) in fuse_bounds(bounds) {
-            res.push_str(&txt[last_stop..start]);
-            res.push_str(&replacement);
-            last_stop = stop;
+        for span in rules.spans(txt) {
+            res.push_str(&txt[last_stop..span.start]);
+            let rule = &rules.rules[span.rule];
+            match &pseudonymizer {
+                Some(pseudonymizer) => {
+                    pseudonymizer.surrogate(rule.format, &txt[span.start..span.end], res)
+                },
+                None if rule.template => {
+                    // Merged spans are expanded with the captures of their first match.
+                    let captures = rules
+                        .matchers
+                        .regex(span.rule)
+                        .and_then(|regex| regex.captures_at(txt, span.start));
+                    match captures {
+                        Some(captures) => captures.expand(&rule.replacement, res),
+                        None => res.push_str(&rule.replacement),
+                    }
+                },
+                None => res.push_str(&rule.replacement),
+            }
+            last_stop = span.end;
         }
         res.push_str(&txt[last_stop..]);
     });
 
     Ok(out.into_series())
 }
+
+#[derive(Deserialize)]
+struct FindSpansKwargs {
+    rules: Vec<RuleKwargs>,
+    overlapping: bool,
+}
+
+fn span_dtype() -> DataType {
+    DataType::Struct(vec![
+        Field::new("start".into(), DataType::UInt32),
+        Field::new("end".into(), DataType::UInt32),
+        Field::new("pattern".into(), DataType::String),
+    ])
+}
+
+fn find_spans_output(input_fields: &[Field], kwargs: FindSpansKwargs) -> PolarsResult<Field> {
+    let field = &input_fields[0];
+    match field.dtype() {
+        DataType::String => {
+            let counts = kwargs
+                .rules
+                .iter()
+                .map(|rule| Field::new(rule.name.as_str().into(), DataType::UInt32))
+                .collect();
+            let fields = vec![
+                Field::new("spans".into(), DataType::List(Box::new(span_dtype()))),
+                Field::new("counts".into(), DataType::Struct(counts)),
+            ];
+            Ok(Field::new(field.name.clone(), DataType::Struct(fields)))
+        },
+        dtype => polars_bail!(InvalidOperation: "expected string dtype, got {}", dtype),
+    }
+}
+
+/// Compiles the matchers of `rules` (as json) into the registry, validating the patterns.
+pub(crate) fn precompile(rules: &str) -> PolarsResult<()> {
+    let rules: Vec<RuleKwargs> = serde_json::from_str(rules)
+        .map_err(|e| polars_err!(ComputeError: "invalid rules: {}", e))?;
+    Rules::new(rules).map(|_| ())
+}
+
+/// Returns the spans `scrub` would replace (or with `overlapping`, all matches) as a list
+/// of `{start, end, pattern}` structs with byte offsets, and the number of spans per pattern.
+#[polars_expr(output_type_func_with_kwargs = find_spans_output)]
+fn find_spans(inputs: &[Series], kwargs: FindSpansKwargs) -> PolarsResult<Series> {
+    let ca: &StringChunked = inputs[0].str()?;
+    let rules = Rules::new(kwargs.rules)?;
+
+    let mut starts: Vec<u32> = Vec::new();
+    let mut ends: Vec<u32> = Vec::new();
+    let mut names: Vec<&str> = Vec::new();
+    let mut offsets: Vec<i64> = Vec::with_capacity(ca.len() + 1);
+    offsets.push(0);
+    let mut counts: Vec<Vec<u32>> = vec![Vec::with_capacity(ca.len()); rules.rules.len()];
+    let mut validity = MutableBitmap::with_capacity(ca.len());
+
+    for opt in ca {
+        counts.iter_mut().for_each(|c| c.push(0));
+        validity.push(opt.is_some());
+        if let Some(txt) = opt {
+            let mut spans = if kwargs.overlapping {
+                rules.matches(txt)
+            } else {
+                rules.spans(txt)
+            };
+            spans.sort_unstable_by_key(|span| (span.start, span.end, span.rule));
+            for span in spans {
+                starts.push(span.start as u32);
+                ends.push(span.end as u32);
+                names.push(&rules.rules[span.rule].name);
+                *counts[span.rule].last_mut().unwrap() += 1;
+            }
+        }
+        offsets.push(starts.len() as i64);
+    }
+
+    let num_spans = starts.len();
+    let spans = StructChunked::from_series(
+        "spans".into(),
+        num_spans,
+        [
+            UInt32Chunked::from_vec("start".into(), starts).into_series(),
+            UInt32Chunked::from_vec("end".into(), ends).into_series(),
+            StringChunked::from_iter_values("pattern".into(), names.into_iter()).into_series(),
+        ]
+        .iter(),
+    )?;
+    let validity = validity.freeze();
+    let spans = list_from_offsets(
+        "spans".into(),
+        spans.into_series(),
+        offsets,
+        Some(validity.clone()),
+    )?;
+    let counts: Vec<Series> = counts
+        .into_iter()
+        .zip(&rules.rules)
+        .map(|(counts, rule)| {
+            UInt32Chunked::from_vec_validity(
+                rule.name.as_str().into(),
+                counts,
+                Some(validity.clone()),
+            )
+            .into_series()
+        })
+        .collect();
+    let counts = StructChunked::from_series("counts".into(), ca.len(), counts.iter())?;
+    StructChunked::from_series(
+        ca.name().clone(),
+        ca.len(),
+        [spans, counts.into_series()].iter(),
+    )
+    .map(|x| x.into_series())
+}
+
+#[derive(Deserialize)]
+struct DetectSecretsKwargs {
+    rules: Vec<SecretRule>,
+}
+
+fn detect_secrets_output(input_fields: &[Field]) -> PolarsResult<Field> {
+    let field = &input_fields[0];
+    match field.dtype() {
+        DataType::String => {
+            let span = DataType::Struct(vec![
+                Field::new("start".into(), DataType::UInt32),
+                Field::new("end".into(), DataType::UInt32),
+                Field::new("rule".into(), DataType::String),
+            ]);
+            Ok(Field::new(
+                field.name.clone(),
+                DataType::List(Box::new(span)),
+            ))
+        },
+        dtype => polars_bail!(InvalidOperation: "expected string dtype, got {}", dtype),
+    }
+}
+
+/// Returns the secrets found by the given rules as a list of `{start, end, rule}` structs
+/// with byte offsets. When matches overlap, the rule that comes first wins.
+#[polars_expr(output_type_func = detect_secrets_output)]
+fn detect_secrets(inputs: &[Series], kwargs: DetectSecretsKwargs) -> PolarsResult<Series> {
+    let ca: &StringChunked = inputs[0].str()?;
+    let rules = kwargs
+        .rules
+        .into_iter()
+        .enumerate()
+        .map(|(priority, rule)| RuleKwargs {
+            name: rule.id().to_string(),
+            matcher: MatcherKwargs::Secret { rule },
+            replacement: String::new(),
+            priority: priority as u32,
+            template: false,
+        })
+        .collect();
+    let rules = Rules::new(rules)?;
+
+    let mut starts: Vec<u32> = Vec::new();
+    let mut ends: Vec<u32> = Vec::new();
+    let mut names: Vec<&str> = Vec::new();
+    let mut offsets: Vec<i64> = Vec::with_capacity(ca.len() + 1);
+    offsets.push(0);
+    let mut validity = MutableBitmap::with_capacity(ca.len());
+
+    for opt in ca {
+        validity.push(opt.is_some());
+        if let Some(txt) = opt {
+            for span in rules.spans(txt) {
+                starts.push(span.start as u32);
+                ends.push(span.end as u32);
+                names.push(&rules.rules[span.rule].name);
+            }
+        }
+        offsets.push(starts.len() as i64);
+    }
+
+    let num_spans = starts.len();
+    let spans = StructChunked::from_series(
+        "spans".into(),
+        num_spans,
+        [
+            UInt32Chunked::from_vec("start".into(), starts).into_series(),
+            UInt32Chunked::from_vec("end".into(), ends).into_series(),
+            StringChunked::from_iter_values("rule".into(), names.into_iter()).into_series(),
+        ]
+        .iter(),
+    )?;
+    list_from_offsets(
+        ca.name().clone(),
+        spans.into_series(),
+        offsets,
+        Some(validity.freeze()),
+    )
+}
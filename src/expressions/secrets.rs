@@ -0,0 +1,123 @@
+//! Built-in rules for secrets and credentials in code (in the spirit of gitleaks and
+//! detect-secrets), used by `detect_secrets`, `scrub` and `find_spans`.
+//!
+//! Rules for tokens with a known format match them directly. Other rules match a secret in
+//! its context (e.g. `password = "..."`), with the secret in the `secret` capture group,
+//! and may require a minimal Shannon entropy to skip placeholders and dummy values.
+use regex::Regex;
+use serde::Deserialize;
+
+use super::pii::shannon_entropy;
+
+#[derive(Deserialize, Clone, Copy, Hash)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SecretRule {
+    AwsAccessKey,
+    AwsSecretKey,
+    GithubToken,
+    GitlabToken,
+    SlackToken,
+    SlackWebhook,
+    StripeKey,
+    GoogleApiKey,
+    OpenaiKey,
+    PrivateKey,
+    Jwt,
+    ConnectionStringPassword,
+    GenericSecret,
+}
+
+impl SecretRule {
+    /// The id of the rule, as used in python.
+    pub(crate) fn id(&self) -> &'static str {
+        match self {
+            Self::AwsAccessKey => "aws_access_key",
+            Self::AwsSecretKey => "aws_secret_key",
+            Self::GithubToken => "github_token",
+            Self::GitlabToken => "gitlab_token",
+            Self::SlackToken => "slack_token",
+            Self::SlackWebhook => "slack_webhook",
+            Self::StripeKey => "stripe_key",
+            Self::GoogleApiKey => "google_api_key",
+            Self::OpenaiKey => "openai_key",
+            Self::PrivateKey => "private_key",
+            Self::Jwt => "jwt",
+            Self::ConnectionStringPassword => "connection_string_password",
+            Self::GenericSecret => "generic_secret",
+        }
+    }
+
+    fn pattern(&self) -> &'static str {
+        match self {
+            Self::AwsAccessKey => r"\b(?:AKIA|ASIA|ABIA|ACCA)[0-9A-Z]{16}\b",
+            Self::AwsSecretKey => {
+                r#"(?i)aws_?secret_?(?:access_?)?key["']?\s*[:=]\s*["']?(?P<secret>[A-Za-z0-9/+]{40})\b"#
+            },
+            Self::GithubToken => {
+                r"\b(?:gh[pousr]_[A-Za-z0-9]{36,255}|github_pat_[A-Za-z0-9_]{82})\b"
+            },
+            Self::GitlabToken => r"\bglpat-[A-Za-z0-9_-]{20,}",
+            Self::SlackToken => r"\bxox[baprs]-[A-Za-z0-9-]{10,}",
+            Self::SlackWebhook => {
+                r"https://hooks\.slack\.com/services/T[A-Z0-9]+/B[A-Z0-9]+/[A-Za-z0-9]+"
+            },
+            Self::StripeKey => r"\b[rs]k_(?:live|test)_[A-Za-z0-9]{16,}",
+            Self::GoogleApiKey => r"\bAIza[A-Za-z0-9_-]{35}",
+            Self::OpenaiKey => r"\bsk-(?:proj-)?[A-Za-z0-9_-]{20,}",
+            Self::PrivateKey => {
+                r"(?s)-----BEGIN[ A-Z0-9]*PRIVATE KEY(?: BLOCK)?-----.*?-----END[ A-Z0-9]*PRIVATE KEY(?: BLOCK)?-----"
+            },
+            Self::Jwt => r"\beyJ[A-Za-z0-9_-]{10,}\.eyJ[A-Za-z0-9_-]{10,}\.[A-Za-z0-9_-]{10,}",
+            Self::ConnectionStringPassword => {
+                r"(?i)\b[a-z][a-z0-9+.-]*://[^\s:/@]+:(?P<secret>[^\s:/@]+)@"
+            },
+            Self::GenericSecret => {
+                r#"(?i)\b(?:password|passwd|pwd|secret|token|api_?key|access_?key|auth_?key|client_?secret)["']?\s*[:=]\s*["'](?P<secret>[^"'\s]{8,})["']"#
+            },
+        }
+    }
+
+    /// The minimal Shannon entropy (in bits per character) of the secret, which is the
+    /// `secret` capture group if the pattern has one, and otherwise the whole match.
+    fn min_entropy(&self) -> f64 {
+        match self {
+            Self::AwsSecretKey | Self::GenericSecret => 3.5,
+            Self::OpenaiKey => 3.0,
+            _ => 0.0,
+        }
+    }
+}
+
+/// Whether `secret` is a placeholder, e.g. `${DB_PASSWORD}`, `<password>` or `****`.
+fn is_placeholder(secret: &str) -> bool {
+    secret.starts_with(['$', '{', '<', '%'])
+        || secret.chars().all(|c| c == '*' || c == 'x' || c == 'X')
+}
+
+/// A compiled secret rule.
+pub(crate) struct SecretMatcher {
+    rule: SecretRule,
+    regex: Regex,
+}
+
+impl SecretMatcher {
+    pub(crate) fn new(rule: SecretRule) -> Self {
+        Self {
+            rule,
+            regex: Regex::new(rule.pattern()).unwrap(),
+        }
+    }
+
+    /// The byte ranges of all secrets in `txt`.
+    pub(crate) fn find_iter<'a>(
+        &'a self,
+        txt: &'a str,
+    ) -> impl Iterator<Item = (usize, usize)> + 'a {
+        self.regex.captures_iter(txt).filter_map(|captures| {
+            let secret = captures.name("secret").or_else(|| captures.get(0))?;
+            let valid = !is_placeholder(secret.as_str())
+                && shannon_entropy(secret.as_str()) >= self.rule.min_entropy();
+            valid.then_some((secret.start(), secret.end()))
+        })
+    }
+}
@@ -1,26 +1,41 @@
 use std::io::Error;
 use std::str::FromStr;
 use std::sync::Arc;
-use std::time::Duration;
 
-use cached::proc_macro::cached;
 use polars::prelude::*;
 use pyo3_polars::derive::polars_expr;
 use serde::Deserialize;
-use tokenizers::tokenizer::Tokenizer;
+use tokenizers::models::ModelWrapper;
+use tokenizers::tokenizer::{
+    Encoding, PaddingDirection, PaddingParams, PaddingStrategy, PostProcessor, Tokenizer,
+    TokenizerBuilder, TruncationDirection, TruncationParams,
+};
+use tokenizers::utils::parallelism::MaybeParallelRefIterator;
+use xxhash_rust::xxh3::xxh3_128;
 
-#[cached(time = 60, time_refresh = true, sync_writes = "by_key")]
-fn tok_from_file(path: String) -> Result<Arc<Tokenizer>, String> {
-    let tok = Tokenizer::from_file(&path)
-        .map_err(|_| format!("Error loading tokenizer from path: {}", &path))?;
-    Ok(Arc::new(tok))
+use super::utils::list_from_offsets;
+use super::words::{Builtin, WordTokenizer};
+use crate::registry;
+
+pub(crate) fn tok_from_file(path: &str) -> Result<Arc<Tokenizer>, String> {
+    registry::load_file("tokenizer", path, |path| {
+        Tokenizer::from_file(path)
+            .map_err(|_| format!("Error loading tokenizer from path: {}", path))
+    })
+}
+
+/// Loads a serialized tokenizer, registered under a hash of the payload rather than the
+/// (possibly very large) payload itself.
+fn tok_from_str(payload: &str) -> Result<Arc<Tokenizer>, String> {
+    let key = json_key(payload);
+    registry::load_keyed("tokenizer", key.clone(), || {
+        Tokenizer::from_str(payload)
+            .map_err(|e| format!("Error loading tokenizer from string ({}): {}", key, e))
+    })
 }
 
-#[cached(time = 60, time_refresh = true, sync_writes = "by_key")]
-fn tok_from_str(payload: String) -> Result<Arc<Tokenizer>, String> {
-    let tok = Tokenizer::from_str(&payload)
-        .map_err(|_| format!("Error loading tokenizer from string: {}", &payload))?;
-    Ok(Arc::new(tok))
+fn json_key(payload: &str) -> String {
+    format!("json:{:032x}", xxh3_128(payload.as_bytes()))
 }
 
 #[derive(Deserialize)]
@@ -30,41 +45,596 @@ struct Kwargs {
 }
 
 impl Kwargs {
-    fn load(self) -> Result<Arc<Tokenizer>, Error> {
+    /// The key the tokenizer is registered under.
+    fn key(&self) -> String {
+        if self.is_path {
+            self.payload.clone()
+        } else {
+            json_key(&self.payload)
+        }
+    }
+
+    fn load(&self) -> Result<Arc<Tokenizer>, Error> {
         let res = if self.is_path {
-            tok_from_file(self.payload)
+            tok_from_file(&self.payload)
         } else {
-            tok_from_str(self.payload)
+            tok_from_str(&self.payload)
         };
         res.map_err(Error::other)
     }
 }
 
-fn outtype(input_fields: &[Field]) -> PolarsResult<Field> {
-    let field = Field::new(
+#[derive(Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+enum Side {
+    Left,
+    Right,
+}
+
+#[derive(Deserialize)]
+struct TokenizeKwargs {
+    #[serde(flatten)]
+    tokenizer: Kwargs,
+    add_special_tokens: bool,
+    output_offsets: bool,
+    output_tokens: bool,
+    output_type_ids: bool,
+    output_attention_mask: bool,
+    max_length: Option<usize>,
+    truncation: Side,
+    stride: usize,
+    overflow: bool,
+    padding: bool,
+    padding_side: Side,
+    pad_id: Option<u32>,
+    batch_size: usize,
+}
+
+impl TokenizeKwargs {
+    /// Whether anything besides the ids is requested, in which case the output is a struct.
+    fn is_struct(&self) -> bool {
+        self.output_offsets
+            || self.output_tokens
+            || self.output_type_ids
+            || self.output_attention_mask
+    }
+
+    fn check(&self) -> PolarsResult<()> {
+        if self.max_length.is_none() && (self.padding || self.overflow) {
+            polars_bail!(InvalidOperation: "padding and overflow require max_length")
+        }
+        if self.batch_size == 0 {
+            polars_bail!(InvalidOperation: "batch_size must be positive")
+        }
+        Ok(())
+    }
+
+    /// The dtype of an output field, given the dtype of its values for a single window.
+    /// Padded windows are fixed size arrays, and overflowing windows are returned as a list.
+    fn shape_dtype(&self, dtype: DataType) -> DataType {
+        let mut dtype = DataType::List(Box::new(dtype));
+        if let (true, Some(n)) = (self.padding, self.max_length) {
+            let DataType::List(inner) = dtype else {
+                unreachable!()
+            };
+            dtype = DataType::Array(inner, n);
+        }
+        if self.overflow {
+            dtype = DataType::List(Box::new(dtype));
+        }
+        dtype
+    }
+
+    /// Loads the tokenizer, configured with the truncation and padding of the kwargs if
+    /// `max_length` is set, replacing any truncation and padding saved with the tokenizer.
+    /// Configured tokenizers are registered under the key of the tokenizer and the settings.
+    fn configure(&self) -> PolarsResult<Arc<Tokenizer>> {
+        let tokenizer = self.tokenizer.load()?;
+        let Some(max_length) = self.max_length else {
+            return Ok(tokenizer);
+        };
+        // The text is truncated to leave room for the special tokens,
+        // which are added to every window.
+        let added = match (self.add_special_tokens, tokenizer.get_post_processor()) {
+            (true, Some(processor)) => processor.added_tokens(false),
+            _ => 0,
+        };
+        if self.stride >= max_length.saturating_sub(added) {
+            polars_bail!(
+                InvalidOperation: "stride ({}) must be less than max_length ({}) minus the number of special tokens ({})",
+                self.stride, max_length, added
+            )
+        }
+        let key = format!(
+            "{} (max_length={}, truncation={:?}, stride={}, padding={}, padding_side={:?}, pad_id={:?})",
+            self.tokenizer.key(),
+            max_length,
+            self.truncation,
+            self.stride,
+            self.padding,
+            self.padding_side,
+            self.pad_id,
+        );
+        let build = || {
+            let truncation = TruncationParams {
+                max_length,
+                stride: self.stride,
+                direction: match self.truncation {
+                    Side::Left => TruncationDirection::Left,
+                    Side::Right => TruncationDirection::Right,
+                },
+                ..Default::default()
+            };
+            let padding = self.padding.then(|| {
+                let pad_id = self
+                    .pad_id
+                    .or_else(|| tokenizer.get_padding().map(|p| p.pad_id))
+                    .unwrap_or(0);
+                PaddingParams {
+                    strategy: PaddingStrategy::Fixed(max_length),
+                    direction: match self.padding_side {
+                        Side::Left => PaddingDirection::Left,
+                        Side::Right => PaddingDirection::Right,
+                    },
+                    pad_id,
+                    pad_token: tokenizer
+                        .id_to_token(pad_id)
+                        .unwrap_or_else(|| "[PAD]".into()),
+                    ..Default::default()
+                }
+            });
+            // The stride is checked above against the special tokens actually added, whereas
+            // `Tokenizer::with_truncation` checks it against them even when they aren't.
+            TokenizerBuilder::new()
+                .with_model(tokenizer.get_model().clone())
+                .with_normalizer(tokenizer.get_normalizer().cloned())
+                .with_pre_tokenizer(tokenizer.get_pre_tokenizer().cloned())
+                .with_post_processor(tokenizer.get_post_processor().cloned())
+                .with_decoder(tokenizer.get_decoder().cloned())
+                .with_added_vocabulary(tokenizer.get_added_vocabulary().clone())
+                .with_truncation(Some(truncation))
+                .with_padding(padding)
+                .build()
+                .map(Tokenizer::from)
+                .map_err(|e| format!("Error configuring tokenizer ({}): {}", key, e))
+        };
+        let res = if self.tokenizer.is_path {
+            registry::load_derived("tokenizer", key.clone(), &self.tokenizer.payload, build)
+        } else {
+            registry::load_keyed("tokenizer", key.clone(), build)
+        };
+        res.map_err(|e| polars_err!(ComputeError: "{}", e))
+    }
+
+    /// Encodes `text` into one or more windows (more than one only if `overflow` is set),
+    /// using a tokenizer configured by `configure`.
+    fn encode(&self, tokenizer: &Tokenizer, text: &str) -> PolarsResult<Vec<Encoding>> {
+        // Offsets are only tracked when requested.
+        let mut encoding = if self.output_offsets {
+            tokenizer.encode(text, self.add_special_tokens)
+        } else {
+            tokenizer.encode_fast(text, self.add_special_tokens)
+        }
+        .map_err(|e| polars_err!(ComputeError: "{}", e))?;
+        let overflowing = if self.overflow {
+            encoding.take_overflowing()
+        } else {
+            vec![]
+        };
+        Ok(std::iter::once(encoding).chain(overflowing).collect())
+    }
+}
+
+fn offsets_dtype() -> DataType {
+    DataType::Struct(vec![
+        Field::new("start".into(), DataType::UInt32),
+        Field::new("end".into(), DataType::UInt32),
+    ])
+}
+
+fn outtype(input_fields: &[Field], kwargs: TokenizeKwargs) -> PolarsResult<Field> {
+    kwargs.check()?;
+    let ids = kwargs.shape_dtype(DataType::UInt32);
+    if !kwargs.is_struct() {
+        return Ok(Field::new(input_fields[0].name.clone(), ids));
+    }
+
+    let mut fields = vec![Field::new("ids".into(), ids)];
+    if kwargs.output_offsets {
+        fields.push(Field::new(
+            "offsets".into(),
+            kwargs.shape_dtype(offsets_dtype()),
+        ));
+    }
+    if kwargs.output_tokens {
+        fields.push(Field::new(
+            "tokens".into(),
+            kwargs.shape_dtype(DataType::String),
+        ));
+    }
+    if kwargs.output_type_ids {
+        fields.push(Field::new(
+            "type_ids".into(),
+            kwargs.shape_dtype(DataType::UInt32),
+        ));
+    }
+    if kwargs.output_attention_mask {
+        fields.push(Field::new(
+            "attention_mask".into(),
+            kwargs.shape_dtype(DataType::UInt8),
+        ));
+    }
+    Ok(Field::new(
         input_fields[0].name.clone(),
-        DataType::List(Box::new(DataType::UInt32)),
-    );
-    Ok(field.clone())
+        DataType::Struct(fields),
+    ))
+}
+
+/// Column-wise accumulator of encoded windows, matching the fields of `outtype`.
+struct EncodingColumns {
+    ids: ListPrimitiveChunkedBuilder<UInt32Type>,
+    starts: Vec<u32>,
+    ends: Vec<u32>,
+    offsets: Vec<i64>,
+    tokens: ListStringChunkedBuilder,
+    type_ids: ListPrimitiveChunkedBuilder<UInt32Type>,
+    attention_mask: ListPrimitiveChunkedBuilder<UInt8Type>,
+    validities: Vec<bool>,
+    // Window offsets and validity of each row, only used with `overflow`.
+    windows: Vec<i64>,
+    row_validities: Vec<bool>,
 }
 
-#[polars_expr(output_type_func = outtype)]
-fn tokenize(inputs: &[Series], kwargs: Kwargs) -> PolarsResult<Series> {
+impl EncodingColumns {
+    fn new(capacity: usize) -> Self {
+        Self {
+            ids: ListPrimitiveChunkedBuilder::new("ids".into(), capacity, 0, DataType::UInt32),
+            starts: Vec::new(),
+            ends: Vec::new(),
+            offsets: vec![0],
+            tokens: ListStringChunkedBuilder::new("tokens".into(), capacity, 0),
+            type_ids: ListPrimitiveChunkedBuilder::new(
+                "type_ids".into(),
+                capacity,
+                0,
+                DataType::UInt32,
+            ),
+            attention_mask: ListPrimitiveChunkedBuilder::new(
+                "attention_mask".into(),
+                capacity,
+                0,
+                DataType::UInt8,
+            ),
+            validities: Vec::with_capacity(capacity),
+            windows: vec![0],
+            row_validities: Vec::with_capacity(capacity),
+        }
+    }
+
+    fn push_window(&mut self, kwargs: &TokenizeKwargs, encoding: Option<&Encoding>) {
+        match encoding {
+            Some(encoding) => {
+                self.ids.append_slice(encoding.get_ids());
+                if kwargs.output_offsets {
+                    for &(start, end) in encoding.get_offsets() {
+                        self.starts.push(start as u32);
+                        self.ends.push(end as u32);
+                    }
+                }
+                if kwargs.output_tokens {
+                    self.tokens
+                        .append_values_iter(encoding.get_tokens().iter().map(|t| t.as_str()));
+                }
+                if kwargs.output_type_ids {
+                    self.type_ids.append_slice(encoding.get_type_ids());
+                }
+                if kwargs.output_attention_mask {
+                    self.attention_mask
+                        .append_values_iter(encoding.get_attention_mask().iter().map(|&m| m as u8));
+                }
+            },
+            None => {
+                self.ids.append_null();
+                self.tokens.append_null();
+                self.type_ids.append_null();
+                self.attention_mask.append_null();
+            },
+        }
+        self.offsets.push(self.starts.len() as i64);
+        self.validities.push(encoding.is_some());
+    }
+
+    fn push(&mut self, kwargs: &TokenizeKwargs, windows: Option<&[Encoding]>) {
+        match (kwargs.overflow, windows) {
+            (true, Some(windows)) => {
+                windows
+                    .iter()
+                    .for_each(|w| self.push_window(kwargs, Some(w)));
+            },
+            (true, None) => {},
+            (false, windows) => self.push_window(kwargs, windows.map(|w| &w[0])),
+        }
+        self.windows.push(self.validities.len() as i64);
+        self.row_validities.push(windows.is_some());
+    }
+
+    /// Reshapes a field with one list per window, as described by `TokenizeKwargs::shape_dtype`.
+    fn shape(&self, kwargs: &TokenizeKwargs, field: Series) -> PolarsResult<Series> {
+        let mut field = field;
+        if let (true, Some(n)) = (kwargs.padding, kwargs.max_length) {
+            let DataType::List(inner) = field.dtype() else {
+                unreachable!()
+            };
+            field = field.cast(&DataType::Array(inner.clone(), n))?;
+        }
+        if kwargs.overflow {
+            field = list_from_offsets(
+                field.name().clone(),
+                field,
+                self.windows.clone(),
+                Some(self.row_validities.iter().copied().collect()),
+            )?;
+        }
+        Ok(field)
+    }
+
+    fn finish(mut self, kwargs: &TokenizeKwargs, name: PlSmallStr) -> PolarsResult<Series> {
+        let ids = self.ids.finish().into_series();
+        if !kwargs.is_struct() {
+            return Ok(self.shape(kwargs, ids)?.with_name(name));
+        }
+
+        let mut fields = vec![ids];
+        if kwargs.output_offsets {
+            let num_tokens = self.starts.len();
+            let offsets = StructChunked::from_series(
+                "offsets".into(),
+                num_tokens,
+                [
+                    UInt32Chunked::from_vec("start".into(), std::mem::take(&mut self.starts))
+                        .into_series(),
+                    UInt32Chunked::from_vec("end".into(), std::mem::take(&mut self.ends))
+                        .into_series(),
+                ]
+                .iter(),
+            )?;
+            fields.push(list_from_offsets(
+                "offsets".into(),
+                offsets.into_series(),
+                std::mem::take(&mut self.offsets),
+                Some(self.validities.iter().copied().collect()),
+            )?);
+        }
+        if kwargs.output_tokens {
+            fields.push(self.tokens.finish().into_series());
+        }
+        if kwargs.output_type_ids {
+            fields.push(self.type_ids.finish().into_series());
+        }
+        if kwargs.output_attention_mask {
+            fields.push(self.attention_mask.finish().into_series());
+        }
+        let fields = fields
+            .into_iter()
+            .map(|field| self.shape(kwargs, field))
+            .collect::<PolarsResult<Vec<_>>>()?;
+        let len = self.row_validities.len();
+        StructChunked::from_series(name, len, fields.iter()).map(|x| x.into_series())
+    }
+}
+
+#[polars_expr(output_type_func_with_kwargs = outtype)]
+fn tokenize(inputs: &[Series], kwargs: TokenizeKwargs) -> PolarsResult<Series> {
     let ca: &StringChunked = inputs[0].str()?;
+    kwargs.check()?;
 
-    let tokenizer = kwargs.load()?;
+    let tokenizer = kwargs.configure()?;
 
-    let mut builder =
-        ListPrimitiveChunkedBuilder::<UInt32Type>::new(ca.name().clone(), 0, 0, DataType::UInt32);
+    let mut columns = EncodingColumns::new(ca.len());
 
-    for opt in ca {
+    // Texts are encoded in parallel a batch at a time, on the rayon pool of `tokenizers`
+    // (honoring `TOKENIZERS_PARALLELISM`), bounding the number of encodings held in memory.
+    let texts: Vec<Option<&str>> = ca.iter().collect();
+    for batch in texts.chunks(kwargs.batch_size) {
+        let encoded = batch
+            .maybe_par_iter_cond(batch.len() > 1)
+            .map(|opt| opt.map(|text| kwargs.encode(&tokenizer, text)).transpose())
+            .collect::<PolarsResult<Vec<_>>>()?;
+        for windows in &encoded {
+            columns.push(&kwargs, windows.as_deref());
+        }
+    }
+    columns.finish(&kwargs, ca.name().clone())
+}
+
+#[derive(Deserialize)]
+struct TokenCountKwargs {
+    #[serde(flatten)]
+    tokenizer: Kwargs,
+    add_special_tokens: bool,
+}
+
+#[polars_expr(output_type = UInt32)]
+fn token_count(inputs: &[Series], kwargs: TokenCountKwargs) -> PolarsResult<Series> {
+    let ca: &StringChunked = inputs[0].str()?;
+
+    let tokenizer = kwargs.tokenizer.load()?;
+
+    let out = ca.iter().map(|opt_text| {
+        opt_text
+            .map(|text| {
+                tokenizer
+                    .encode_fast(text, kwargs.add_special_tokens)
+                    .map(|encoding| encoding.len() as u32)
+                    .map_err(|e| polars_err!(ComputeError: "{}", e))
+            })
+            .transpose()
+    });
+    Ok(out.collect::<PolarsResult<UInt32Chunked>>()?.into_series())
+}
+
+#[derive(Deserialize)]
+struct DetokenizeKwargs {
+    #[serde(flatten)]
+    tokenizer: Kwargs,
+    skip_special_tokens: bool,
+}
+
+#[polars_expr(output_type = String)]
+fn detokenize(inputs: &[Series], kwargs: DetokenizeKwargs) -> PolarsResult<Series> {
+    let s = inputs[0].cast(&DataType::List(Box::new(DataType::UInt32)))?;
+    let ca: &ListChunked = s.list()?;
+
+    let tokenizer = kwargs.tokenizer.load()?;
+
+    let mut builder = StringChunkedBuilder::new(ca.name().clone(), ca.len());
+    let mut ids: Vec<u32> = Vec::new();
+
+    // Ids that are null or don't fit a `UInt32` are null after the cast, and ids that
+    // aren't in the vocabulary would be skipped by `decode`, so both are rejected.
+    for (row, opt) in ca.amortized_iter().enumerate() {
         match opt {
-            Some(text) => {
-                builder.append_slice(tokenizer.encode_fast(text, false).unwrap().get_ids())
+            Some(tokens) => {
+                let tokens = tokens.as_ref().u32()?;
+                if tokens.null_count() > 0 {
+                    polars_bail!(
+                        ComputeError: "row {} of detokenize: token ids must be non-null and in the range of UInt32",
+                        row
+                    )
+                }
+                ids.clear();
+                ids.extend(tokens.into_no_null_iter());
+                if let Some(id) = ids.iter().find(|&&id| tokenizer.id_to_token(id).is_none()) {
+                    polars_bail!(
+                        ComputeError: "row {} of detokenize: token id {} is not in the vocabulary",
+                        row, id
+                    )
+                }
+                let text = tokenizer
+                    .decode(&ids, kwargs.skip_special_tokens)
+                    .map_err(|e| polars_err!(ComputeError: "{}", e))?;
+                builder.append_value(text);
             },
             None => builder.append_null(),
         }
     }
     Ok(builder.finish().into_series())
-    //Ok(UInt32Chunked::from_iter(out).into_series())
+}
+
+#[derive(Deserialize)]
+struct TokenizerStatsKwargs {
+    #[serde(flatten)]
+    tokenizer: Kwargs,
+    word_pattern: String,
+    word_tokenizer: Option<Builtin>,
+}
+
+fn tokenizer_stats_output(input_fields: &[Field]) -> PolarsResult<Field> {
+    let field = &input_fields[0];
+    match field.dtype() {
+        DataType::String => {
+            let fields = vec![
+                Field::new("num_tokens".into(), DataType::UInt32),
+                Field::new("num_words".into(), DataType::UInt32),
+                Field::new("tokens_per_word".into(), DataType::Float32),
+                Field::new("tokens_per_byte".into(), DataType::Float32),
+                Field::new("unk_ratio".into(), DataType::Float32),
+                Field::new("byte_fallback_ratio".into(), DataType::Float32),
+            ];
+            Ok(Field::new(field.name.clone(), DataType::Struct(fields)))
+        },
+        dtype => polars_bail!(InvalidOperation: "expected string dtype, got {}", dtype),
+    }
+}
+
+/// The id of the unknown token of the tokenizer's model, if it has one.
+fn unk_id(tokenizer: &Tokenizer) -> Option<u32> {
+    let unk_token = match tokenizer.get_model() {
+        ModelWrapper::BPE(model) => model.unk_token.clone()?,
+        ModelWrapper::WordPiece(model) => model.unk_token.clone(),
+        ModelWrapper::WordLevel(model) => model.unk_token.clone(),
+        // Unigram models only expose their unknown token id through serialization.
+        ModelWrapper::Unigram(model) => {
+            let model = serde_json::to_value(model).ok()?;
+            return model.get("unk_id")?.as_u64().map(|id| id as u32);
+        },
+    };
+    tokenizer.token_to_id(&unk_token)
+}
+
+/// Whether `token` is a byte fallback token, such as `<0x0A>`.
+fn is_byte_fallback(token: &str) -> bool {
+    token.len() == 6
+        && token.starts_with("<0x")
+        && token.ends_with('>')
+        && token[3..5].bytes().all(|b| b.is_ascii_hexdigit())
+}
+
+fn ratio(num: usize, den: usize) -> Option<f32> {
+    (den > 0).then(|| ((num as f64) / (den as f64)) as f32)
+}
+
+#[polars_expr(output_type_func = tokenizer_stats_output)]
+fn tokenizer_stats(inputs: &[Series], kwargs: TokenizerStatsKwargs) -> PolarsResult<Series> {
+    let ca: &StringChunked = inputs[0].str()?;
+    let words = WordTokenizer::new(&kwargs.word_pattern, kwargs.word_tokenizer)?;
+
+    let tokenizer = kwargs.tokenizer.load()?;
+    let unk_id = unk_id(&tokenizer);
+
+    let mut num_tokens: Vec<Option<u32>> = Vec::with_capacity(ca.len());
+    let mut num_words: Vec<Option<u32>> = Vec::with_capacity(ca.len());
+    let mut tokens_per_word: Vec<Option<f32>> = Vec::with_capacity(ca.len());
+    let mut tokens_per_byte: Vec<Option<f32>> = Vec::with_capacity(ca.len());
+    let mut unk_ratio: Vec<Option<f32>> = Vec::with_capacity(ca.len());
+    let mut byte_fallback_ratio: Vec<Option<f32>> = Vec::with_capacity(ca.len());
+
+    for opt in ca {
+        let Some(text) = opt else {
+            num_tokens.push(None);
+            num_words.push(None);
+            tokens_per_word.push(None);
+            tokens_per_byte.push(None);
+            unk_ratio.push(None);
+            byte_fallback_ratio.push(None);
+            continue;
+        };
+        let encoding = tokenizer
+            .encode_fast(text, false)
+            .map_err(|e| polars_err!(ComputeError: "{}", e))?;
+        let tokens = encoding.len();
+        let words = words.tokens(text).count();
+        let unks = encoding
+            .get_ids()
+            .iter()
+            .filter(|&&id| Some(id) == unk_id)
+            .count();
+        let byte_fallbacks = encoding
+            .get_tokens()
+            .iter()
+            .filter(|token| is_byte_fallback(token))
+            .count();
+        num_tokens.push(Some(tokens as u32));
+        num_words.push(Some(words as u32));
+        tokens_per_word.push(ratio(tokens, words));
+        tokens_per_byte.push(ratio(tokens, text.len()));
+        unk_ratio.push(ratio(unks, tokens));
+        byte_fallback_ratio.push(ratio(byte_fallbacks, tokens));
+    }
+
+    let uint = |name: &str, values: Vec<Option<u32>>| {
+        UInt32Chunked::from_iter_options(name.into(), values.into_iter()).into_series()
+    };
+    let float = |name: &str, values: Vec<Option<f32>>| {
+        Float32Chunked::from_iter_options(name.into(), values.into_iter()).into_series()
+    };
+    let fields = [
+        uint("num_tokens", num_tokens),
+        uint("num_words", num_words),
+        float("tokens_per_word", tokens_per_word),
+        float("tokens_per_byte", tokens_per_byte),
+        float("unk_ratio", unk_ratio),
+        float("byte_fallback_ratio", byte_fallback_ratio),
+    ];
+    StructChunked::from_series(ca.name().clone(), ca.len(), fields.iter()).map(|x| x.into_series())
 }
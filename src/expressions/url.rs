@@ -0,0 +1,216 @@
+//! URL expressions: extraction of URLs from text, parsing into their components
+//! (including the registered domain, using the embedded Public Suffix List),
+//! and normalization for deduplication.
+use std::sync::LazyLock;
+
+use ::url::Url;
+use polars::prelude::*;
+use polars_arrow::bitmap::MutableBitmap;
+use pyo3_polars::derive::polars_expr;
+use regex::Regex;
+use serde::Deserialize;
+
+use super::utils::list_from_offsets;
+
+static URL: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)\b(?:(?:https?|ftp)://|www\.)[^\s<>"'`]+"#).unwrap());
+
+/// Strips trailing punctuation from a URL candidate, keeping closing brackets
+/// that are balanced within the URL, e.g. in `https://en.wikipedia.org/wiki/Rust_(language)`.
+fn trim_url(mut url: &str) -> &str {
+    loop {
+        let Some(last) = url.chars().next_back() else {
+            return url;
+        };
+        let open = match last {
+            '.' | ',' | ';' | ':' | '!' | '?' | '*' => None,
+            ')' => Some('('),
+            ']' => Some('['),
+            '}' => Some('{'),
+            _ => return url,
+        };
+        if let Some(open) = open {
+            if url.matches(open).count() >= url.matches(last).count() {
+                return url;
+            }
+        }
+        url = &url[..url.len() - 1];
+    }
+}
+
+fn list_of_strings_output(input_fields: &[Field]) -> PolarsResult<Field> {
+    let field = &input_fields[0];
+    match field.dtype() {
+        DataType::String => Ok(Field::new(
+            field.name.clone(),
+            DataType::List(Box::new(DataType::String)),
+        )),
+        dtype => polars_bail!(InvalidOperation: "expected string dtype, got {}", dtype),
+    }
+}
+
+/// Extracts all URLs (starting with `http://`, `https://`, `ftp://` or `www.`) from each text.
+#[polars_expr(output_type_func = list_of_strings_output)]
+fn extract_urls(inputs: &[Series]) -> PolarsResult<Series> {
+    let ca: &StringChunked = inputs[0].str()?;
+
+    let mut urls: Vec<&str> = Vec::new();
+    let mut offsets: Vec<i64> = Vec::with_capacity(ca.len() + 1);
+    offsets.push(0);
+    let mut validity = MutableBitmap::with_capacity(ca.len());
+
+    for opt in ca {
+        validity.push(opt.is_some());
+        if let Some(txt) = opt {
+            urls.extend(
+                URL.find_iter(txt)
+                    .map(|m| trim_url(m.as_str()))
+                    .filter(|url| url.contains('.')),
+            );
+        }
+        offsets.push(urls.len() as i64);
+    }
+
+    let urls = StringChunked::from_iter_values(ca.name().clone(), urls.into_iter());
+    list_from_offsets(
+        ca.name().clone(),
+        urls.into_series(),
+        offsets,
+        Some(validity.freeze()),
+    )
+}
+
+/// Parses `url`, which may lack a scheme (e.g. `www.example.com/page`), in which case
+/// it is parsed as an http URL. Returns the URL and whether it had a scheme.
+fn parse(url: &str) -> Option<(Url, bool)> {
+    let url = url.trim();
+    match Url::parse(url) {
+        Ok(parsed) if parsed.has_host() => Some((parsed, true)),
+        Ok(_) | Err(::url::ParseError::RelativeUrlWithoutBase) => {
+            let parsed = Url::parse(&format!("http://{}", url)).ok()?;
+            parsed.has_host().then_some((parsed, false))
+        },
+        Err(_) => None,
+    }
+}
+
+fn parse_url_output(input_fields: &[Field]) -> PolarsResult<Field> {
+    let field = &input_fields[0];
+    match field.dtype() {
+        DataType::String => {
+            let fields = ["scheme", "host", "domain", "suffix", "path", "query"]
+                .into_iter()
+                .map(|name| Field::new(name.into(), DataType::String))
+                .collect();
+            Ok(Field::new(field.name.clone(), DataType::Struct(fields)))
+        },
+        dtype => polars_bail!(InvalidOperation: "expected string dtype, got {}", dtype),
+    }
+}
+
+/// Parses URLs into a struct of their `scheme`, `host`, registered `domain` (e.g. `example.co.uk`
+/// for `www.example.co.uk`) and public `suffix` (e.g. `co.uk`), `path` and `query`.
+/// Unparseable URLs are null.
+#[polars_expr(output_type_func = parse_url_output)]
+fn parse_url(inputs: &[Series]) -> PolarsResult<Series> {
+    let ca: &StringChunked = inputs[0].str()?;
+
+    let mut scheme: Vec<Option<String>> = Vec::with_capacity(ca.len());
+    let mut host: Vec<Option<String>> = Vec::with_capacity(ca.len());
+    let mut domain: Vec<Option<String>> = Vec::with_capacity(ca.len());
+    let mut suffix: Vec<Option<String>> = Vec::with_capacity(ca.len());
+    let mut path: Vec<Option<String>> = Vec::with_capacity(ca.len());
+    let mut query: Vec<Option<String>> = Vec::with_capacity(ca.len());
+    let mut validity = MutableBitmap::with_capacity(ca.len());
+
+    for opt in ca {
+        let parsed = opt.and_then(parse);
+        validity.push(parsed.is_some());
+        let Some((url, has_scheme)) = parsed else {
+            scheme.push(None);
+            host.push(None);
+            domain.push(None);
+            suffix.push(None);
+            path.push(None);
+            query.push(None);
+            continue;
+        };
+        // IP addresses have no (registered) domain.
+        let name = url.domain().map(|name| name.trim_end_matches('.'));
+        scheme.push(has_scheme.then(|| url.scheme().to_string()));
+        host.push(url.host_str().map(str::to_string));
+        domain.push(name.and_then(psl::domain_str).map(str::to_string));
+        suffix.push(name.and_then(psl::suffix_str).map(str::to_string));
+        path.push(Some(url.path().to_string()));
+        query.push(url.query().map(str::to_string));
+    }
+
+    let string = |name: &str, values: Vec<Option<String>>| {
+        StringChunked::from_iter_options(name.into(), values.into_iter()).into_series()
+    };
+    let fields = [
+        string("scheme", scheme),
+        string("host", host),
+        string("domain", domain),
+        string("suffix", suffix),
+        string("path", path),
+        string("query", query),
+    ];
+    let out = StructChunked::from_series(ca.name().clone(), ca.len(), fields.iter())?;
+    Ok(out
+        .with_outer_validity(Some(validity.freeze()))
+        .into_series())
+}
+
+#[derive(Deserialize)]
+struct NormalizeUrlKwargs {
+    /// Query parameters to remove, where a trailing `*` matches any suffix (e.g. `utm_*`).
+    strip_params: Vec<String>,
+    keep_fragment: bool,
+    sort_query: bool,
+}
+
+impl NormalizeUrlKwargs {
+    fn strip(&self, param: &str) -> bool {
+        let name = param.split('=').next().unwrap_or_default();
+        self.strip_params
+            .iter()
+            .any(|pattern| match pattern.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == pattern,
+            })
+    }
+
+    /// Normalizes `url`. The URL parser lowercases the scheme and host, removes default ports
+    /// and resolves dot segments, after which the stripped parameters are removed from the
+    /// query, and the fragment is removed unless `keep_fragment`.
+    fn normalize(&self, url: &str) -> Option<String> {
+        let (mut url, _) = parse(url)?;
+        if let Some(query) = url.query() {
+            let mut params: Vec<&str> = query
+                .split('&')
+                .filter(|param| !param.is_empty() && !self.strip(param))
+                .collect();
+            if self.sort_query {
+                params.sort_unstable();
+            }
+            let query = params.join("&");
+            url.set_query((!query.is_empty()).then_some(query.as_str()));
+        }
+        if !self.keep_fragment {
+            url.set_fragment(None);
+        }
+        Some(url.into())
+    }
+}
+
+/// Normalizes URLs for deduplication and comparison. Unparseable URLs are null.
+#[polars_expr(output_type = String)]
+fn normalize_url(inputs: &[Series], kwargs: NormalizeUrlKwargs) -> PolarsResult<Series> {
+    let ca: &StringChunked = inputs[0].str()?;
+    let out: StringChunked = ca
+        .iter()
+        .map(|opt| opt.and_then(|url| kwargs.normalize(url)))
+        .collect();
+    Ok(out.with_name(ca.name().clone()).into_series())
+}
@@ -0,0 +1,29 @@
+use polars::prelude::*;
+use polars_arrow::array::ListArray;
+use polars_arrow::bitmap::Bitmap;
+use polars_arrow::offset::OffsetsBuffer;
+
+/// Wraps a flat `values` series into a list series,
+/// where row `i` holds `values[offsets[i]..offsets[i + 1]]`.
+pub(crate) fn list_from_offsets(
+    name: PlSmallStr,
+    values: Series,
+    offsets: Vec<i64>,
+    validity: Option<Bitmap>,
+) -> PolarsResult<Series> {
+    let values = values.rechunk();
+    let inner_dtype = values.dtype().clone();
+    let values = values.chunks()[0].clone();
+    let offsets: OffsetsBuffer<i64> = offsets.try_into()?;
+    let arrow_dtype = ListArray::<i64>::default_datatype(values.dtype().clone());
+    let arr = ListArray::<i64>::try_new(arrow_dtype, offsets, values, validity)?;
+    // SAFETY: the list array is built from the physical representation of `inner_dtype`.
+    let ca = unsafe {
+        ListChunked::from_chunks_and_dtype(
+            name,
+            vec![Box::new(arr)],
+            DataType::List(Box::new(inner_dtype)),
+        )
+    };
+    Ok(ca.into_series())
+}
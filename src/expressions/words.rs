@@ -0,0 +1,93 @@
+//! Word tokenizers shared by the text signal expressions (minhash, repetition signals, ...).
+//!
+//! Besides a user supplied regex, a number of built-in tokenizers can be selected by name,
+//! which are considerably faster than the equivalent regexes.
+use std::str::{CharIndices, SplitWhitespace};
+use std::sync::Arc;
+
+use polars::prelude::*;
+use regex::{Matches, Regex};
+use serde::Deserialize;
+use unicode_segmentation::{UnicodeSegmentation, UnicodeWords};
+
+use crate::registry;
+
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Builtin {
+    /// Words according to the Unicode word boundaries of UAX#29, without punctuation and whitespace.
+    UnicodeWords,
+    /// Runs of non-whitespace characters.
+    Whitespace,
+    /// Single characters.
+    Chars,
+    /// Single bytes.
+    Bytes,
+}
+
+/// Compiles `pattern`, caching the regex in the pattern registry,
+/// so that expressions don't recompile their patterns for every batch.
+pub(crate) fn cached_regex(pattern: &str) -> Result<Arc<Regex>, String> {
+    registry::load_pattern("regex", pattern.to_string(), || {
+        Regex::new(pattern).map_err(|e| format!("invalid regex {:?}: {}", pattern, e))
+    })
+}
+
+pub(crate) enum WordTokenizer {
+    Regex(Arc<Regex>),
+    Builtin(Builtin),
+}
+
+impl WordTokenizer {
+    /// Uses the `builtin` tokenizer if given, and otherwise the regex `pattern`.
+    pub(crate) fn new(pattern: &str, builtin: Option<Builtin>) -> PolarsResult<Self> {
+        Ok(match builtin {
+            Some(builtin) => Self::Builtin(builtin),
+            None => {
+                Self::Regex(cached_regex(pattern).map_err(|e| polars_err!(ComputeError: "{}", e))?)
+            },
+        })
+    }
+
+    /// Iterates over the tokens of `txt`. Tokens are returned as bytes,
+    /// since the `bytes` tokenizer can split characters.
+    pub(crate) fn tokens<'a>(&'a self, txt: &'a str) -> Tokens<'a> {
+        match self {
+            Self::Regex(regex) => Tokens::Regex(regex.find_iter(txt)),
+            Self::Builtin(Builtin::UnicodeWords) => Tokens::UnicodeWords(txt.unicode_words()),
+            Self::Builtin(Builtin::Whitespace) => Tokens::Whitespace(txt.split_whitespace()),
+            Self::Builtin(Builtin::Chars) => Tokens::Chars(txt, txt.char_indices()),
+            Self::Builtin(Builtin::Bytes) => Tokens::Bytes(txt.as_bytes().iter()),
+        }
+    }
+}
+
+pub(crate) enum Tokens<'a> {
+    Regex(Matches<'a, 'a>),
+    UnicodeWords(UnicodeWords<'a>),
+    Whitespace(SplitWhitespace<'a>),
+    Chars(&'a str, CharIndices<'a>),
+    Bytes(std::slice::Iter<'a, u8>),
+}
+
+impl<'a> Iterator for Tokens<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<&'a [u8]> {
+        match self {
+            Self::Regex(matches) => matches.next().map(|m| m.as_str().as_bytes()),
+            Self::UnicodeWords(words) => words.next().map(str::as_bytes),
+            Self::Whitespace(words) => words.next().map(str::as_bytes),
+            Self::Chars(txt, chars) => chars
+                .next()
+                .map(|(i, c)| &txt.as_bytes()[i..i + c.len_utf8()]),
+            Self::Bytes(bytes) => bytes.next().map(std::slice::from_ref),
+        }
+    }
+}
+
+/// The number of characters in a token, not counting UTF-8 continuation bytes,
+/// so that the lengths of the tokens of a text add up to its number of characters.
+pub(crate) fn char_len(token: &[u8]) -> usize {
+    token.iter().filter(|&&b| (b & 0xC0) != 0x80).count()
+}
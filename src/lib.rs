@@ -1,11 +1,140 @@
-#![feature(iter_map_windows)]
 mod expressions;
+mod registry;
+mod training;
+use std::time::Duration;
+
+use pyo3::exceptions::{PyIOError, PyValueError};
 use pyo3::prelude::*;
-use pyo3_polars::PolarsAllocator;
+use pyo3_polars::{PolarsAllocator, PySeries};
+
+use crate::expressions::{fasttext, scrub, tokenize, words};
+use crate::training::TrainOptions;
+
+/// Loads the `kind` model at `path` into the model registry, optionally pinning it.
+#[pyfunction]
+#[pyo3(signature = (kind, path, pin=false))]
+fn preload_model(py: Python, kind: &str, path: &str, pin: bool) -> PyResult<()> {
+    type Load = fn(&str) -> Result<(), String>;
+    let (kind, load): (&'static str, Load) = match kind {
+        "fasttext" => ("fasttext", |path| fasttext::load_model(path).map(|_| ())),
+        "tokenizer" => ("tokenizer", |path| {
+            tokenize::tok_from_file(path).map(|_| ())
+        }),
+        _ => {
+            return Err(PyValueError::new_err(format!(
+                "unknown model kind: {}",
+                kind
+            )))
+        },
+    };
+    // Pin before loading, so that the model can't be evicted before it is pinned.
+    // A failed load removes the (empty) entry again.
+    py.detach(|| {
+        if pin {
+            registry::pin(kind, path);
+        }
+        load(path)
+    })
+    .map_err(PyIOError::new_err)
+}
+
+/// Unpins a registered model, returning whether the model was found.
+#[pyfunction]
+fn unpin_model(kind: &str, path: &str) -> bool {
+    registry::set_pinned(kind, path, false)
+}
+
+/// Evicts matching models from the registry, returning the number of evicted models.
+#[pyfunction]
+#[pyo3(signature = (kind=None, path=None))]
+fn evict_models(kind: Option<&str>, path: Option<&str>) -> usize {
+    registry::evict(kind, path)
+}
+
+/// Lists the registered models as `(kind, key, pinned, idle_seconds)` tuples.
+#[pyfunction]
+fn list_models() -> Vec<(&'static str, String, bool, f64)> {
+    registry::list()
+        .into_iter()
+        .map(|info| (info.kind, info.key, info.pinned, info.idle.as_secs_f64()))
+        .collect()
+}
+
+/// Sets the TTL (in seconds) and the maximum number of registered models.
+#[pyfunction]
+#[pyo3(signature = (ttl=None, max_models=None))]
+fn set_model_cache(ttl: Option<f64>, max_models: Option<usize>) -> PyResult<()> {
+    let ttl = ttl
+        .map(Duration::try_from_secs_f64)
+        .transpose()
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    registry::configure(ttl, max_models);
+    Ok(())
+}
+
+/// Compiles the scrub `rules` (as json) and the word tokenizer `word_patterns` into the
+/// pattern registry, raising a `ValueError` for invalid patterns.
+#[pyfunction]
+fn precompile_patterns(py: Python, rules: &str, word_patterns: Vec<String>) -> PyResult<()> {
+    py.detach(|| {
+        scrub::precompile(rules).map_err(|e| e.to_string())?;
+        word_patterns
+            .iter()
+            .try_for_each(|pattern| words::cached_regex(pattern).map(|_| ()))
+    })
+    .map_err(PyValueError::new_err)
+}
+
+/// Trains a tokenizer on a string series and saves it as json to `path`.
+#[pyfunction]
+#[pyo3(signature = (
+    texts, path, model, vocab_size, min_frequency, special_tokens, unk_token,
+    lowercase, strip_accents, unicode_normalization, pre_tokenizer,
+))]
+#[allow(clippy::too_many_arguments)]
+fn train_tokenizer(
+    py: Python,
+    texts: PySeries,
+    path: &str,
+    model: String,
+    vocab_size: usize,
+    min_frequency: u64,
+    special_tokens: Vec<String>,
+    unk_token: Option<String>,
+    lowercase: bool,
+    strip_accents: bool,
+    unicode_normalization: Option<String>,
+    pre_tokenizer: Option<String>,
+) -> PyResult<()> {
+    let texts = texts.0;
+    let texts = texts
+        .str()
+        .map_err(|e| PyValueError::new_err(e.to_string()))?;
+    let options = TrainOptions {
+        model,
+        vocab_size,
+        min_frequency,
+        special_tokens,
+        unk_token,
+        lowercase,
+        strip_accents,
+        unicode_normalization,
+        pre_tokenizer,
+    };
+    py.detach(|| training::train(texts, path, options))
+        .map_err(PyValueError::new_err)
+}
 
 #[pymodule]
 fn _internal(_py: Python, m: &Bound<PyModule>) -> PyResult<()> {
     m.add("__version__", env!("CARGO_PKG_VERSION"))?;
+    m.add_function(wrap_pyfunction!(preload_model, m)?)?;
+    m.add_function(wrap_pyfunction!(unpin_model, m)?)?;
+    m.add_function(wrap_pyfunction!(evict_models, m)?)?;
+    m.add_function(wrap_pyfunction!(list_models, m)?)?;
+    m.add_function(wrap_pyfunction!(set_model_cache, m)?)?;
+    m.add_function(wrap_pyfunction!(precompile_patterns, m)?)?;
+    m.add_function(wrap_pyfunction!(train_tokenizer, m)?)?;
     Ok(())
 }
 
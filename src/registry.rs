@@ -0,0 +1,281 @@
+//! Process-wide registry of loaded models (fasttext models, tokenizers, ...),
+//! shared by all expressions and controlled from python.
+//!
+//! Models are keyed by their kind and a key (usually a path). Models loaded from a file
+//! are reloaded when the modification time of the file changes. Unpinned models are
+//! evicted when they have been unused for longer than the configured TTL, or when more
+//! than the configured number of models are loaded (least recently used first).
+//! There is no background thread, so these limits are enforced whenever the registry
+//! is accessed, i.e. when a model is requested, (un)pinned or listed.
+//!
+//! Compiled patterns (regexes, scrub matchers, word lists) are cheap to rebuild, and are
+//! kept in a separate registry, bounded to the most recently used patterns, which doesn't
+//! count toward the limits of the models and isn't listed.
+use std::any::Any;
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+
+type Model = Arc<dyn Any + Send + Sync>;
+type Key = (&'static str, String);
+
+struct Loaded {
+    model: Model,
+    mtime: Option<SystemTime>,
+}
+
+/// Holds a (possibly not yet) loaded model. Loading happens while holding the slot lock,
+/// so concurrent requests for the same model wait for a single load,
+/// without blocking requests for other models.
+#[derive(Default)]
+struct Slot {
+    loaded: Mutex<Option<Loaded>>,
+}
+
+struct Entry {
+    slot: Arc<Slot>,
+    pinned: bool,
+    last_used: Instant,
+}
+
+#[derive(Default)]
+struct Registry {
+    entries: HashMap<Key, Entry>,
+    ttl: Option<Duration>,
+    max_models: Option<usize>,
+}
+
+pub(crate) struct ModelInfo {
+    pub kind: &'static str,
+    pub key: String,
+    pub pinned: bool,
+    pub idle: Duration,
+}
+
+/// The number of compiled patterns kept in the pattern registry.
+const MAX_PATTERNS: usize = 256;
+
+/// The default TTL of unpinned models.
+const DEFAULT_TTL: Duration = Duration::from_secs(60);
+
+static MODELS: LazyLock<Mutex<Registry>> = LazyLock::new(|| {
+    Mutex::new(Registry {
+        ttl: Some(DEFAULT_TTL),
+        ..Default::default()
+    })
+});
+
+static PATTERNS: LazyLock<Mutex<Registry>> = LazyLock::new(|| {
+    Mutex::new(Registry {
+        max_models: Some(MAX_PATTERNS),
+        ..Default::default()
+    })
+});
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // A panicking loader leaves the slot empty, so it is safe to ignore poisoning.
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+impl Registry {
+    fn evict_stale(&mut self, now: Instant) {
+        if let Some(ttl) = self.ttl {
+            self.entries
+                .retain(|_, entry| entry.pinned || now.duration_since(entry.last_used) <= ttl);
+        }
+        if let Some(max_models) = self.max_models {
+            while self.entries.len() > max_models {
+                let lru = self
+                    .entries
+                    .iter()
+                    .filter(|(_, entry)| !entry.pinned)
+                    .min_by_key(|(_, entry)| entry.last_used)
+                    .map(|(key, _)| key.clone());
+                match lru {
+                    Some(key) => {
+                        self.entries.remove(&key);
+                    },
+                    None => break,
+                }
+            }
+        }
+    }
+
+    fn entry(&mut self, key: Key, now: Instant) -> &mut Entry {
+        let entry = self.entries.entry(key).or_insert_with(|| Entry {
+            slot: Default::default(),
+            pinned: false,
+            last_used: now,
+        });
+        entry.last_used = now;
+        entry
+    }
+
+    fn slot(&mut self, key: Key) -> Arc<Slot> {
+        let now = Instant::now();
+        let entry = self.entry(key, now);
+        let slot = entry.slot.clone();
+        self.evict_stale(now);
+        slot
+    }
+}
+
+fn get<T: Send + Sync + 'static>(
+    registry: &Mutex<Registry>,
+    kind: &'static str,
+    key: String,
+    mtime: Option<SystemTime>,
+    load: impl FnOnce() -> Result<T, String>,
+) -> Result<Arc<T>, String> {
+    let slot = lock(registry).slot((kind, key.clone()));
+    let mut loaded = lock(&slot.loaded);
+    if let Some(current) = loaded.as_ref() {
+        if current.mtime == mtime {
+            return current
+                .model
+                .clone()
+                .downcast::<T>()
+                .map_err(|_| format!("registered {} model {} has the wrong type", kind, key));
+        }
+    }
+    let model = match load() {
+        Ok(model) => Arc::new(model),
+        Err(e) => {
+            // Don't keep entries for models that never loaded.
+            if loaded.is_none() {
+                let mut registry = lock(registry);
+                let key = (kind, key);
+                if registry
+                    .entries
+                    .get(&key)
+                    .is_some_and(|entry| Arc::ptr_eq(&entry.slot, &slot))
+                {
+                    registry.entries.remove(&key);
+                }
+            }
+            return Err(e);
+        },
+    };
+    *loaded = Some(Loaded {
+        model: model.clone(),
+        mtime,
+    });
+    Ok(model)
+}
+
+/// Returns the `kind` model loaded from `path`, loading it with `load` if it is not
+/// registered, or if the file has been modified since it was loaded.
+pub(crate) fn load_file<T: Send + Sync + 'static>(
+    kind: &'static str,
+    path: &str,
+    load: impl FnOnce(&str) -> Result<T, String>,
+) -> Result<Arc<T>, String> {
+    let mtime = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    get(&MODELS, kind, path.to_string(), mtime, || load(path))
+}
+
+/// Returns the `kind` model registered under `key`, loading it with `load` if it is not registered.
+pub(crate) fn load_keyed<T: Send + Sync + 'static>(
+    kind: &'static str,
+    key: String,
+    load: impl FnOnce() -> Result<T, String>,
+) -> Result<Arc<T>, String> {
+    get(&MODELS, kind, key, None, load)
+}
+
+/// Returns the `kind` model registered under `key`, derived from the file at `path`
+/// (e.g. a configured copy of a model), reloading it as for `load_file`.
+pub(crate) fn load_derived<T: Send + Sync + 'static>(
+    kind: &'static str,
+    key: String,
+    path: &str,
+    load: impl FnOnce() -> Result<T, String>,
+) -> Result<Arc<T>, String> {
+    let mtime = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    get(&MODELS, kind, key, mtime, load)
+}
+
+/// Returns the `kind` pattern compiled from the file at `path`, as for `load_file`,
+/// but cached in the pattern registry.
+pub(crate) fn load_pattern_file<T: Send + Sync + 'static>(
+    kind: &'static str,
+    path: &str,
+    load: impl FnOnce(&str) -> Result<T, String>,
+) -> Result<Arc<T>, String> {
+    let mtime = std::fs::metadata(path).and_then(|m| m.modified()).ok();
+    get(&PATTERNS, kind, path.to_string(), mtime, || load(path))
+}
+
+/// Returns the `kind` pattern registered under `key`, as for `load_keyed`,
+/// but cached in the pattern registry.
+pub(crate) fn load_pattern<T: Send + Sync + 'static>(
+    kind: &'static str,
+    key: String,
+    load: impl FnOnce() -> Result<T, String>,
+) -> Result<Arc<T>, String> {
+    get(&PATTERNS, kind, key, None, load)
+}
+
+/// Pins the `kind` model registered under `key`, registering it (to be loaded by the next
+/// request) if it isn't, so that a model can't be evicted between loading and pinning it.
+pub(crate) fn pin(kind: &'static str, key: &str) {
+    let mut registry = lock(&MODELS);
+    let now = Instant::now();
+    registry.entry((kind, key.to_string()), now).pinned = true;
+    registry.evict_stale(now);
+}
+
+/// Pins (or unpins) a registered model, returning whether the model was found.
+/// Pinned models are never evicted by the TTL or LRU limits.
+pub(crate) fn set_pinned(kind: &str, key: &str, pinned: bool) -> bool {
+    let mut registry = lock(&MODELS);
+    registry.evict_stale(Instant::now());
+    let entry = registry
+        .entries
+        .iter_mut()
+        .find(|((k, name), _)| *k == kind && name == key);
+    match entry {
+        Some((_, entry)) => {
+            entry.pinned = pinned;
+            true
+        },
+        None => false,
+    }
+}
+
+/// Evicts all models matching the given kind and key (all models if both are `None`),
+/// including pinned models. Returns the number of evicted models.
+pub(crate) fn evict(kind: Option<&str>, key: Option<&str>) -> usize {
+    let mut registry = lock(&MODELS);
+    let before = registry.entries.len();
+    registry.entries.retain(|(k, name), _| {
+        !(kind.is_none_or(|kind| kind == *k) && key.is_none_or(|key| key == name))
+    });
+    before - registry.entries.len()
+}
+
+/// Sets the TTL and maximum number of loaded models (`None` meaning unlimited).
+pub(crate) fn configure(ttl: Option<Duration>, max_models: Option<usize>) {
+    let mut registry = lock(&MODELS);
+    registry.ttl = ttl;
+    registry.max_models = max_models;
+    registry.evict_stale(Instant::now());
+}
+
+pub(crate) fn list() -> Vec<ModelInfo> {
+    let mut registry = lock(&MODELS);
+    let now = Instant::now();
+    registry.evict_stale(now);
+    let mut models: Vec<ModelInfo> = registry
+        .entries
+        .iter()
+        .map(|((kind, key), entry)| ModelInfo {
+            kind,
+            key: key.clone(),
+            pinned: entry.pinned,
+            idle: now.duration_since(entry.last_used),
+        })
+        .collect();
+    models.sort_by_key(|info| info.idle);
+    models
+}
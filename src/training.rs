@@ -0,0 +1,172 @@
+//! Training of `tokenizers` models directly from polars string columns.
+use polars::prelude::*;
+use tokenizers::decoders::wordpiece::WordPiece as WordPieceDecoder;
+use tokenizers::decoders::DecoderWrapper;
+use tokenizers::models::bpe::{BpeTrainer, BPE};
+use tokenizers::models::unigram::{Unigram, UnigramTrainer};
+use tokenizers::models::wordpiece::{WordPiece, WordPieceTrainer};
+use tokenizers::models::{ModelWrapper, TrainerWrapper};
+use tokenizers::normalizers::{
+    Lowercase, NormalizerWrapper, Sequence, StripAccents, NFC, NFD, NFKC, NFKD,
+};
+use tokenizers::pre_tokenizers::byte_level::ByteLevel;
+use tokenizers::pre_tokenizers::metaspace::Metaspace;
+use tokenizers::pre_tokenizers::whitespace::Whitespace;
+use tokenizers::pre_tokenizers::PreTokenizerWrapper;
+use tokenizers::{AddedToken, PostProcessorWrapper, Tokenizer, TokenizerBuilder};
+
+pub(crate) struct TrainOptions {
+    pub model: String,
+    pub vocab_size: usize,
+    pub min_frequency: u64,
+    pub special_tokens: Vec<String>,
+    pub unk_token: Option<String>,
+    pub lowercase: bool,
+    pub strip_accents: bool,
+    pub unicode_normalization: Option<String>,
+    pub pre_tokenizer: Option<String>,
+}
+
+fn normalizer(options: &TrainOptions) -> Result<Option<NormalizerWrapper>, String> {
+    let mut normalizers: Vec<NormalizerWrapper> = Vec::new();
+    match options.unicode_normalization.as_deref() {
+        None => {},
+        Some("nfc") => normalizers.push(NFC.into()),
+        Some("nfd") => normalizers.push(NFD.into()),
+        Some("nfkc") => normalizers.push(NFKC.into()),
+        Some("nfkd") => normalizers.push(NFKD.into()),
+        Some(form) => return Err(format!("unknown unicode normalization: {}", form)),
+    }
+    if options.strip_accents {
+        // Accents can only be stripped once decomposed.
+        normalizers.push(NFD.into());
+        normalizers.push(StripAccents.into());
+    }
+    if options.lowercase {
+        normalizers.push(Lowercase.into());
+    }
+    Ok(match normalizers.len() {
+        0 => None,
+        1 => normalizers.pop(),
+        _ => Some(Sequence::new(normalizers).into()),
+    })
+}
+
+/// Trains a tokenizer on the non-null values of `texts`, and saves it as json to `path`.
+pub(crate) fn train(
+    texts: &StringChunked,
+    path: &str,
+    options: TrainOptions,
+) -> Result<(), String> {
+    let mut special_tokens: Vec<AddedToken> = options
+        .special_tokens
+        .iter()
+        .map(|token| AddedToken::from(token.clone(), true))
+        .collect();
+    // The BPE and WordPiece trainers only put special tokens in the vocabulary, so the unknown
+    // token has to be one of them (the Unigram trainer adds it by itself).
+    let unk_token = match options.model.as_str() {
+        "wordpiece" => Some(options.unk_token.clone().unwrap_or("[UNK]".into())),
+        "bpe" => options.unk_token.clone(),
+        _ => None,
+    };
+    if let Some(unk_token) = &unk_token {
+        if !special_tokens
+            .iter()
+            .any(|token| &token.content == unk_token)
+        {
+            special_tokens.insert(0, AddedToken::from(unk_token.clone(), true));
+        }
+    }
+
+    // Each model defaults to the pre-tokenizer it is usually trained with.
+    let pre_tokenizer = match options.pre_tokenizer.as_deref() {
+        Some(pre_tokenizer) => pre_tokenizer,
+        None => match options.model.as_str() {
+            "bpe" => "byte_level",
+            "unigram" => "metaspace",
+            _ => "whitespace",
+        },
+    };
+    let (pre_tokenizer, decoder): (PreTokenizerWrapper, Option<DecoderWrapper>) =
+        match pre_tokenizer {
+            "byte_level" => (
+                ByteLevel::default().into(),
+                Some(ByteLevel::default().into()),
+            ),
+            "metaspace" => (
+                Metaspace::default().into(),
+                Some(Metaspace::default().into()),
+            ),
+            "whitespace" => (Whitespace.into(), None),
+            _ => return Err(format!("unknown pre-tokenizer: {}", pre_tokenizer)),
+        };
+    // Byte level models start from all 256 bytes, so that they never produce unknown tokens.
+    let initial_alphabet = match &pre_tokenizer {
+        PreTokenizerWrapper::ByteLevel(_) => ByteLevel::alphabet(),
+        _ => Default::default(),
+    };
+
+    let (model, mut trainer, decoder): (ModelWrapper, TrainerWrapper, _) =
+        match options.model.as_str() {
+            "bpe" => {
+                let mut model = BPE::builder();
+                if let Some(unk_token) = unk_token {
+                    model = model.unk_token(unk_token);
+                }
+                let trainer = BpeTrainer::builder()
+                    .vocab_size(options.vocab_size)
+                    .min_frequency(options.min_frequency)
+                    .special_tokens(special_tokens)
+                    .initial_alphabet(initial_alphabet.iter().copied().collect())
+                    .show_progress(false)
+                    .build();
+                (
+                    model.build().map_err(|e| e.to_string())?.into(),
+                    trainer.into(),
+                    decoder,
+                )
+            },
+            "wordpiece" => {
+                let model = WordPiece::builder()
+                    .unk_token(unk_token.unwrap_or_default())
+                    .build()
+                    .map_err(|e| e.to_string())?;
+                let trainer = WordPieceTrainer::builder()
+                    .vocab_size(options.vocab_size)
+                    .min_frequency(options.min_frequency)
+                    .special_tokens(special_tokens)
+                    .initial_alphabet(initial_alphabet.iter().copied().collect())
+                    .show_progress(false)
+                    .build();
+                let decoder = decoder.or(Some(WordPieceDecoder::default().into()));
+                (model.into(), trainer.into(), decoder)
+            },
+            "unigram" => {
+                let trainer = UnigramTrainer::builder()
+                    .vocab_size(options.vocab_size as u32)
+                    .special_tokens(special_tokens)
+                    .unk_token(options.unk_token.clone())
+                    .initial_alphabet(initial_alphabet)
+                    .show_progress(false)
+                    .build()
+                    .map_err(|e| e.to_string())?;
+                (Unigram::default().into(), trainer.into(), decoder)
+            },
+            model => return Err(format!("unknown tokenizer model: {}", model)),
+        };
+
+    let mut tokenizer: Tokenizer = TokenizerBuilder::new()
+        .with_model(model)
+        .with_normalizer(normalizer(&options)?)
+        .with_pre_tokenizer(Some(pre_tokenizer))
+        .with_post_processor(None::<PostProcessorWrapper>)
+        .with_decoder(decoder)
+        .build()
+        .map_err(|e| e.to_string())?
+        .into();
+    tokenizer
+        .train(&mut trainer, texts.iter().flatten())
+        .map_err(|e| e.to_string())?;
+    tokenizer.save(path, false).map_err(|e| e.to_string())
+}